@@ -0,0 +1,266 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use bevy::prelude::*;
+use rand::{seq::IteratorRandom, Rng};
+
+use crate::{
+    maze::{Cell, DistanceMap},
+    movement::Movement,
+    Game, GameRng, Goal, GridPos, Item, Labyrinth, Player,
+};
+
+// 相手の方が近いアイテムの価値にかける倍率
+const CONTESTED_RATE: f32 = 0.3;
+
+// 寄り道するマスまでの最大の距離
+const DETOUR_RANGE: u32 = 4;
+
+// コンピュータの強さ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+// コンピュータが動かすプレイヤー
+#[derive(Component, Debug)]
+pub struct Bot {
+    pub difficulty: Difficulty,
+    // 向かっているマス
+    target: Option<IVec2>,
+    // 目標の前に寄り道するマス
+    waypoint: Option<IVec2>,
+    // 経路の重みのばらつきを決める値 (考え直すたびに変える)
+    route_seed: u64,
+    // 次に目標を考え直すまでの時間 (秒)
+    think_left: f32,
+    // 動かしたい方向 (長さは 1 まで)
+    pub direction: Vec2,
+}
+
+impl Difficulty {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    // 人 -> Easy -> Normal -> Hard -> 人 の順に切り替える
+    pub const fn cycle(difficulty: Option<Difficulty>) -> Option<Difficulty> {
+        match difficulty {
+            None => Some(Difficulty::Easy),
+            Some(Difficulty::Easy) => Some(Difficulty::Normal),
+            Some(Difficulty::Normal) => Some(Difficulty::Hard),
+            Some(Difficulty::Hard) => None,
+        }
+    }
+
+    // 目標を考え直す間隔 (秒)
+    const fn reaction_time(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.8,
+            Difficulty::Normal => 0.4,
+            Difficulty::Hard => 0.1,
+        }
+    }
+
+    // 間違った場所に向かう確率
+    const fn mistake_rate(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.25,
+            Difficulty::Normal => 0.08,
+            Difficulty::Hard => 0.,
+        }
+    }
+
+    // 経路を選ぶ時にマスの重みに足す値の最大 (大きいほど遠回りする)
+    const fn route_noise(self) -> u32 {
+        match self {
+            Difficulty::Easy => 3,
+            Difficulty::Normal => 1,
+            Difficulty::Hard => 0,
+        }
+    }
+
+    // 目標の前に近くのマスへ寄り道する確率
+    const fn detour_rate(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.3,
+            Difficulty::Normal => 0.1,
+            Difficulty::Hard => 0.,
+        }
+    }
+}
+
+impl Bot {
+    pub fn new(difficulty: Difficulty) -> Bot {
+        Bot {
+            difficulty,
+            target: None,
+            waypoint: None,
+            route_seed: 0,
+            think_left: 0.,
+            direction: Vec2::ZERO,
+        }
+    }
+
+    // to までの経路 (pos と to を含む)
+    // Hard 以外はマスの重みにばらつきを付けるので、最短経路を通るとは限らない
+    fn path(&self, labyrinth: &Labyrinth, pos: IVec2, to: IVec2) -> Option<Vec<Cell>> {
+        let noise = self.difficulty.route_noise();
+        if noise == 0 {
+            return labyrinth.0.shortest_path(pos.into(), to.into());
+        }
+        labyrinth.0.weighted_path(pos.into(), to.into(), |cell| {
+            let mut hasher = DefaultHasher::new();
+            (cell, self.route_seed).hash(&mut hasher);
+            1 + (hasher.finish() % (noise as u64 + 1)) as u32
+        })
+    }
+}
+
+// コンピュータから見たアイテムの価値
+fn item_value(item: &Item) -> f32 {
+    match *item {
+        Item::Point(value) => value as f32,
+        Item::SpeedBoost | Item::Freeze => 2.,
+        Item::WallPass => 1.,
+        Item::Reveal => 0.5,
+    }
+}
+
+// 設定に合わせてプレイヤーをコンピュータにしたり人に戻したりする
+pub fn sync_bots(
+    mut commands: Commands,
+    player_query: Query<(Entity, &Player, Option<&Bot>)>,
+    game: Res<Game>,
+) {
+    for (entity, player, bot) in &player_query {
+        let difficulty = game.cpu.get(player.id).copied().flatten();
+        match (difficulty, bot) {
+            (Some(difficulty), Some(bot)) if bot.difficulty == difficulty => (),
+            (Some(difficulty), _) => {
+                commands.entity(entity).insert(Bot::new(difficulty));
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<Bot>();
+            }
+            (None, None) => (),
+        }
+    }
+}
+
+// 目標を決めて、そこまでの経路の次のマスに向かう
+// 目標は一定の間隔でしか考え直さないので、弱いほど反応が遅れる
+// 弱いほど遠回りしたり、近くのマスに寄り道したりする
+pub fn bot_think(
+    mut bot_query: Query<(&mut Bot, &Player, &GridPos, &Transform)>,
+    player_query: Query<(&Player, &GridPos)>,
+    target_query: Query<(&GridPos, Option<&Item>), Or<(With<Item>, With<Goal>)>>,
+    labyrinth: Res<Labyrinth>,
+    game: Res<Game>,
+    mut rng: ResMut<GameRng>,
+    time: Res<Time>,
+) {
+    for (mut bot, player, pos, transform) in &mut bot_query {
+        bot.think_left -= time.delta_seconds();
+        if bot.think_left <= 0. || bot.target.is_none() {
+            bot.think_left = bot.difficulty.reaction_time();
+            bot.route_seed = rng.0.gen();
+
+            let opponents: Vec<DistanceMap> = player_query
+                .iter()
+                .filter(|(other, _)| other.id != player.id)
+                .map(|(_, other_pos)| labyrinth.0.distances_from(other_pos.0.into()))
+                .collect();
+            let targets: Vec<(IVec2, f32)> = target_query
+                .iter()
+                .map(|(target, item)| (target.0, item.map_or(1., item_value)))
+                .collect();
+
+            bot.target = if rng.0.gen_bool(bot.difficulty.mistake_rate()) {
+                // 間違えて適当な通路に向かう
+                labyrinth.0.passages().choose(&mut rng.0).map(IVec2::from)
+            } else {
+                choose_target(&labyrinth, pos.0, &targets, &opponents, bot.difficulty)
+            };
+            bot.waypoint = if rng.0.gen_bool(bot.difficulty.detour_rate()) {
+                let own = labyrinth.0.distances_from(pos.0.into());
+                labyrinth
+                    .0
+                    .passages()
+                    .filter(|&cell| {
+                        own.get(cell)
+                            .is_some_and(|d| (2..=DETOUR_RANGE).contains(&d))
+                    })
+                    .choose(&mut rng.0)
+                    .map(IVec2::from)
+            } else {
+                None
+            };
+        }
+
+        let path = bot
+            .waypoint
+            .or(bot.target)
+            .and_then(|to| bot.path(&labyrinth, pos.0, to));
+        bot.direction = match path.as_deref() {
+            Some([_, next, ..]) => {
+                let next = IVec2::from(*next);
+                match game.movement {
+                    Movement::Grid => (next - pos.0).as_vec2(),
+                    // マスの中心に向かって、角で引っかからないようにする
+                    Movement::Free => (game.grid_to_world(next) - transform.translation.truncate())
+                        .normalize_or_zero(),
+                }
+            }
+            // 寄り道が終わったら目標に向かう
+            _ if bot.waypoint.take().is_some() => Vec2::ZERO,
+            // 着いた、または辿り着けない
+            _ => {
+                bot.target = None;
+                Vec2::ZERO
+            }
+        };
+    }
+}
+
+// 一番良い目標を選ぶ
+// Easy は一番近いものに向かい、それ以外は価値と距離、相手の方が近いかを考える
+fn choose_target(
+    labyrinth: &Labyrinth,
+    pos: IVec2,
+    targets: &[(IVec2, f32)],
+    opponents: &[DistanceMap],
+    difficulty: Difficulty,
+) -> Option<IVec2> {
+    let own = labyrinth.0.distances_from(pos.into());
+    let reachable = targets
+        .iter()
+        .filter_map(|&(target, value)| Some((target, value, own.get(target.into())?)));
+
+    if difficulty == Difficulty::Easy {
+        return reachable
+            .min_by_key(|&(_, _, distance)| distance)
+            .map(|(target, _, _)| target);
+    }
+
+    reachable
+        .map(|(target, value, distance)| {
+            let contested = opponents.iter().any(|opponent| {
+                opponent
+                    .get(target.into())
+                    .is_some_and(|other| other < distance)
+            });
+            let rate = if contested { CONTESTED_RATE } else { 1. };
+            (target, value * rate / (distance + 1) as f32)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(target, _)| target)
+}
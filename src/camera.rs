@@ -0,0 +1,172 @@
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    prelude::*,
+    render::{
+        camera::{ScalingMode, Viewport},
+        view::RenderLayers,
+    },
+    window::PrimaryWindow,
+};
+
+use crate::{fog::FogMode, Game, Player, RoundRules};
+
+// UI だけを描くカメラのレイヤー (何も置かない)
+const UI_LAYER: u8 = RenderLayers::TOTAL_LAYERS as u8 - 1;
+
+// プレイヤーを追いかける時の一マスの大きさ (ピクセル)
+const FOLLOW_CELL_SIZE: f32 = 48.;
+
+// カメラがプレイヤーに追いつく速さ (大きいほど速い)
+const FOLLOW_RATE: f32 = 8.;
+
+// 画面全体のカメラ (UI はこのカメラに描く)
+#[derive(Component)]
+pub struct MainCamera;
+
+// プレイヤーごとの画面のカメラ
+#[derive(Component)]
+pub struct PlayerCamera {
+    pub id: usize,
+}
+
+// そのプレイヤーの画面にだけ映るレイヤー
+pub fn player_layer(id: usize) -> RenderLayers {
+    RenderLayers::layer(id as u8 + 1)
+}
+
+// プレイヤーごとに画面を分けるか
+fn split_screen(rules: &RoundRules) -> bool {
+    rules.split_screen || rules.fog != FogMode::Off
+}
+
+// 画面を分ける時はプレイヤーごとにカメラを作る
+// 全体のカメラは UI だけを、分けた画面の上に描く
+// 分割画面の設定なら、迷路の大きさに関係なく同じ大きさのマスでプレイヤーを追いかける
+pub fn setup_cameras(
+    mut commands: Commands,
+    mut main_query: Query<(Entity, &mut Camera, &mut Camera2d), With<MainCamera>>,
+    camera_query: Query<Entity, With<PlayerCamera>>,
+    game: Res<Game>,
+    rules: Res<RoundRules>,
+) {
+    for entity in &camera_query {
+        commands.entity(entity).despawn();
+    }
+
+    let split = split_screen(&rules);
+    for (entity, mut camera, mut camera_2d) in &mut main_query {
+        if split {
+            camera.order = rules.player_num as isize;
+            camera_2d.clear_color = ClearColorConfig::None;
+            commands
+                .entity(entity)
+                .insert(RenderLayers::layer(UI_LAYER));
+        } else {
+            camera.order = 0;
+            camera_2d.clear_color = ClearColorConfig::Default;
+            commands.entity(entity).remove::<RenderLayers>();
+        }
+    }
+    if !split {
+        return;
+    }
+
+    // 迷路全体が収まる大きさ
+    let (width, height) = (
+        game.block_size.x * game.maze_width() as f32,
+        game.block_size.y * game.maze_height() as f32,
+    );
+    for (id, &start) in game.player_init_pos.iter().enumerate() {
+        let mut camera = Camera2dBundle::default();
+        camera.camera.order = id as isize;
+        if rules.split_screen {
+            camera.projection.scaling_mode =
+                ScalingMode::WindowSize(FOLLOW_CELL_SIZE / game.block_size.x);
+            let pos = game.grid_to_world(start);
+            camera.transform.translation.x = pos.x;
+            camera.transform.translation.y = pos.y;
+        } else {
+            camera.projection.scaling_mode = ScalingMode::AutoMin {
+                min_width: width,
+                min_height: height,
+            };
+        }
+        commands.spawn((
+            camera,
+            RenderLayers::layer(0).with(id as u8 + 1),
+            UiCameraConfig { show_ui: false },
+            PlayerCamera { id },
+        ));
+    }
+}
+
+// ウィンドウを人数に合わせて分ける (3 人以上なら 2 列)
+// 最後の行に一人だけ残る時は、その画面を横いっぱいに広げて隙間を作らない
+pub fn layout_cameras(
+    mut camera_query: Query<(&mut Camera, &PlayerCamera)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    rules: Res<RoundRules>,
+) {
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    let player_num = rules.player_num as u32;
+    let columns = player_num.clamp(1, 2);
+    let rows = player_num.div_ceil(columns);
+    let size = UVec2::new(
+        window.physical_width() / columns,
+        window.physical_height() / rows,
+    );
+
+    for (mut camera, player_camera) in &mut camera_query {
+        let id = player_camera.id as u32;
+        let (column, row) = (id % columns, id / columns);
+        let position = UVec2::new(column, row) * size;
+        let alone = id + 1 == player_num && column == 0;
+        let width = if alone {
+            window.physical_width()
+        } else {
+            size.x
+        };
+        let viewport = Viewport {
+            physical_position: position,
+            physical_size: UVec2::new(width, size.y).max(UVec2::ONE),
+            ..default()
+        };
+        if camera
+            .viewport
+            .as_ref()
+            .map(|v| (v.physical_position, v.physical_size))
+            != Some((viewport.physical_position, viewport.physical_size))
+        {
+            camera.viewport = Some(viewport);
+        }
+    }
+}
+
+// 分割画面ではカメラをプレイヤーに少し遅れてついて行かせる
+pub fn follow_players(
+    mut camera_query: Query<(&mut Transform, &PlayerCamera), Without<Player>>,
+    player_query: Query<(&Transform, &Player)>,
+    rules: Res<RoundRules>,
+    time: Res<Time>,
+) {
+    if !rules.split_screen {
+        return;
+    }
+    let rate = (FOLLOW_RATE * time.delta_seconds()).min(1.);
+
+    for (mut transform, player_camera) in &mut camera_query {
+        let Some((target, _)) = player_query
+            .iter()
+            .find(|(_, player)| player.id == player_camera.id)
+        else {
+            continue;
+        };
+        let pos = transform
+            .translation
+            .truncate()
+            .lerp(target.translation.truncate(), rate);
+        transform.translation = pos.extend(transform.translation.z);
+    }
+}
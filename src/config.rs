@@ -0,0 +1,37 @@
+use std::fs;
+
+use bevy::prelude::*;
+
+// "名前 = 値" を一行ずつ並べた設定ファイル
+// 空の行と '#' で始まる行は飛ばす
+pub trait ConfigFile: Default {
+    const PATH: &'static str;
+
+    // 一行分の設定を反映する (読めない設定なら false)
+    fn apply(&mut self, name: &str, value: &str) -> bool;
+
+    // 無い時や読めない行は初期設定のまま
+    fn load() -> Self {
+        let mut config = Self::default();
+        if let Ok(text) = fs::read_to_string(Self::PATH) {
+            config.parse(&text);
+        }
+        config
+    }
+
+    fn parse(&mut self, text: &str) {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let applied = line
+                .split_once('=')
+                .is_some_and(|(name, value)| self.apply(name.trim(), value.trim()));
+            if !applied {
+                warn!("{}:{}: 読めない設定です: {line}", Self::PATH, number + 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,596 @@
+use std::fs;
+
+use bevy::{
+    ecs::system::SystemParam,
+    input::gamepad::GamepadConnectionEvent,
+    prelude::*,
+    reflect::{DynamicEnum, DynamicVariant},
+};
+
+use crate::{config::ConfigFile, ButtonAction, Game, Player, TEXT_COLOR};
+
+// これより小さくスティックを倒しても動かない
+const STICK_DEADZONE: f32 = 0.25;
+
+// プレイヤーの操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+// 操作ごとのキー
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct Controls {
+    keys: [KeyCode; 4],
+}
+
+// 全プレイヤーの操作設定
+#[derive(Resource, Debug, Clone)]
+pub struct KeyConfig {
+    pub players: Vec<Controls>,
+}
+
+// 操作設定の画面
+#[derive(Resource, Default)]
+pub struct ControlsScreen {
+    pub open: bool,
+    // キーを押すのを待っている (プレイヤー, 操作)
+    pub waiting: Option<(usize, Action)>,
+}
+
+#[derive(Component)]
+pub struct ControlsScreenRoot;
+
+// プレイヤーごとのゲームパッド (つながった順に割り当てる)
+#[derive(Resource, Default)]
+pub struct GamepadSlots(Vec<Option<Gamepad>>);
+
+// キーボードとゲームパッドの入力をまとめて読む
+#[derive(SystemParam)]
+pub struct PlayerInput<'w> {
+    key: Res<'w, Input<KeyCode>>,
+    gamepad_slots: Res<'w, GamepadSlots>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+    buttons: Res<'w, Input<GamepadButton>>,
+}
+
+impl Action {
+    pub const ALL: [Action; 4] = [Action::Up, Action::Down, Action::Left, Action::Right];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Left => "left",
+            Action::Right => "right",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    // 動く方向
+    const fn direction(self) -> Vec2 {
+        match self {
+            Action::Up => Vec2::Y,
+            Action::Down => Vec2::NEG_Y,
+            Action::Left => Vec2::NEG_X,
+            Action::Right => Vec2::X,
+        }
+    }
+}
+
+impl Controls {
+    pub const fn new(up: KeyCode, down: KeyCode, left: KeyCode, right: KeyCode) -> Controls {
+        Controls {
+            keys: [up, down, left, right],
+        }
+    }
+
+    pub fn key(&self, action: Action) -> KeyCode {
+        self.keys[action as usize]
+    }
+
+    pub fn set(&mut self, action: Action, key: KeyCode) {
+        self.keys[action as usize] = key;
+    }
+
+    // 押されているキーから動く方向を求める
+    pub fn direction(&self, input: &Input<KeyCode>) -> Vec2 {
+        Action::ALL
+            .into_iter()
+            .filter(|&action| input.pressed(self.key(action)))
+            .map(Action::direction)
+            .sum()
+    }
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        KeyConfig {
+            players: vec![
+                Controls::new(KeyCode::W, KeyCode::S, KeyCode::A, KeyCode::D),
+                Controls::new(KeyCode::Up, KeyCode::Down, KeyCode::Left, KeyCode::Right),
+                Controls::new(KeyCode::I, KeyCode::K, KeyCode::J, KeyCode::L),
+                Controls::new(
+                    KeyCode::Numpad8,
+                    KeyCode::Numpad5,
+                    KeyCode::Numpad4,
+                    KeyCode::Numpad6,
+                ),
+            ],
+        }
+    }
+}
+
+// 操作設定を保存するファイル ("p1.up = W" の形の行)
+impl ConfigFile for KeyConfig {
+    const PATH: &'static str = "controls.cfg";
+
+    fn apply(&mut self, name: &str, key: &str) -> bool {
+        let Some((player, action)) = name.split_once('.') else {
+            return false;
+        };
+        let player = player
+            .strip_prefix('p')
+            .and_then(|player| player.parse::<usize>().ok())
+            .and_then(|player| player.checked_sub(1));
+
+        let controls = player.and_then(|player| self.players.get_mut(player));
+        match (controls, Action::from_name(action), key_from_name(key)) {
+            (Some(controls), Some(action), Some(key)) => {
+                controls.set(action, key);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl KeyConfig {
+    pub fn save(&self) {
+        let mut text = String::from("# 操作設定 (キーの名前は bevy の KeyCode)\n");
+        for (player, controls) in self.players.iter().enumerate() {
+            for action in Action::ALL {
+                text += &format!(
+                    "p{}.{} = {:?}\n",
+                    player + 1,
+                    action.name(),
+                    controls.key(action)
+                );
+            }
+        }
+        if let Err(err) = fs::write(Self::PATH, text) {
+            warn!("{} に保存できません: {err}", Self::PATH);
+        }
+    }
+}
+
+impl GamepadSlots {
+    pub fn get(&self, player: usize) -> Option<Gamepad> {
+        self.0.get(player).copied().flatten()
+    }
+
+    // 空いている一番前のプレイヤーに割り当てる
+    fn connect(&mut self, gamepad: Gamepad) {
+        if self.0.contains(&Some(gamepad)) {
+            return;
+        }
+        match self.0.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => *slot = Some(gamepad),
+            None => self.0.push(Some(gamepad)),
+        }
+    }
+
+    // 外れても他のプレイヤーの割り当ては変えない
+    fn disconnect(&mut self, gamepad: Gamepad) {
+        for slot in &mut self.0 {
+            if *slot == Some(gamepad) {
+                *slot = None;
+            }
+        }
+    }
+}
+
+// 左スティックと十字キーから動く方向を求める
+fn gamepad_direction(
+    gamepad: Gamepad,
+    axes: &Axis<GamepadAxis>,
+    buttons: &Input<GamepadButton>,
+) -> Vec2 {
+    let axis = |axis_type| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.);
+    let mut stick = Vec2::new(
+        axis(GamepadAxisType::LeftStickX),
+        axis(GamepadAxisType::LeftStickY),
+    );
+    if stick.length() < STICK_DEADZONE {
+        stick = Vec2::ZERO;
+    }
+
+    let dpad: Vec2 = [
+        (GamepadButtonType::DPadUp, Vec2::Y),
+        (GamepadButtonType::DPadDown, Vec2::NEG_Y),
+        (GamepadButtonType::DPadLeft, Vec2::NEG_X),
+        (GamepadButtonType::DPadRight, Vec2::X),
+    ]
+    .into_iter()
+    .filter(|&(button, _)| buttons.pressed(GamepadButton::new(gamepad, button)))
+    .map(|(_, direction)| direction)
+    .sum();
+
+    (stick + dpad).clamp_length_max(1.)
+}
+
+impl PlayerInput<'_> {
+    // プレイヤーが動かしたい方向 (長さは 1 まで)
+    // キーボードはいつでも使え、ゲームパッドが割り当てられていればそれも使える
+    pub fn direction(&self, controls: &Controls, player: &Player) -> Vec2 {
+        let mut direction = controls.direction(&self.key);
+        if let Some(gamepad) = self.gamepad_slots.get(player.id) {
+            direction += gamepad_direction(gamepad, &self.axes, &self.buttons);
+        }
+        // 斜めに入力しても速くならないようにする
+        direction.clamp_length_max(1.)
+    }
+}
+
+// ゲームパッドがつながったらプレイヤーに割り当てる
+pub fn assign_gamepads(
+    mut slots: ResMut<GamepadSlots>,
+    mut events: EventReader<GamepadConnectionEvent>,
+) {
+    for event in events.read() {
+        if event.connected() {
+            slots.connect(event.gamepad);
+            info!("{:?} をつなぎました: {:?}", event.gamepad, slots.0);
+        } else {
+            slots.disconnect(event.gamepad);
+            info!("{:?} が外れました: {:?}", event.gamepad, slots.0);
+        }
+    }
+}
+
+// キーの名前 (KeyCode の列挙子名) から KeyCode を作る
+fn key_from_name(name: &str) -> Option<KeyCode> {
+    KeyCode::from_reflect(&DynamicEnum::new(name, DynamicVariant::Unit))
+}
+
+// 設定画面が開いている間はプレイヤーを動かさない
+pub fn screen_closed(screen: Res<ControlsScreen>) -> bool {
+    !screen.open
+}
+
+// 待っている操作に、押されたキーを割り当てる
+// Escape で取り消す
+pub fn rebind_key(
+    mut screen: ResMut<ControlsScreen>,
+    mut config: ResMut<KeyConfig>,
+    mut player_query: Query<(&mut Controls, &Player)>,
+    input: Res<Input<KeyCode>>,
+) {
+    let Some((player, action)) = screen.waiting else {
+        return;
+    };
+    let Some(&key) = input.get_just_pressed().next() else {
+        return;
+    };
+
+    screen.waiting = None;
+    if key == KeyCode::Escape {
+        return;
+    }
+
+    config.players[player].set(action, key);
+    config.save();
+
+    // 今動いているプレイヤーにも反映する
+    for (mut controls, id) in &mut player_query {
+        if id.id == player {
+            *controls = config.players[player].clone();
+        }
+    }
+}
+
+// 設定画面を作り直す
+pub fn controls_screen_update(
+    mut commands: Commands,
+    screen: Res<ControlsScreen>,
+    config: Res<KeyConfig>,
+    game: Res<Game>,
+    root_query: Query<Entity, With<ControlsScreenRoot>>,
+) {
+    if !screen.is_changed() && !config.is_changed() && !game.is_changed() {
+        return;
+    }
+
+    for entity in &root_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !screen.open {
+        return;
+    }
+
+    let button_style = Style {
+        width: Val::Px(240.),
+        height: Val::Px(36.),
+        margin: UiRect::all(Val::Px(3.)),
+        align_items: AlignItems::Center,
+        justify_content: JustifyContent::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 26.,
+        color: Color::BLACK,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.8).into(),
+                // 迷路の設定より手前に表示する
+                z_index: ZIndex::Global(1),
+                ..default()
+            },
+            ControlsScreenRoot,
+        ))
+        .with_children(|parent| {
+            let message = if screen.waiting.is_some() {
+                "Press a key (Esc to cancel)"
+            } else {
+                "Controls"
+            };
+            parent.spawn(TextBundle::from_section(
+                message,
+                TextStyle {
+                    font_size: 40.,
+                    color: TEXT_COLOR,
+                    ..default()
+                },
+            ));
+
+            parent.spawn(NodeBundle::default()).with_children(|parent| {
+                for (player, controls) in config.players.iter().enumerate() {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                margin: UiRect::all(Val::Px(10.)),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            let controller = match game.cpu.get(player).copied().flatten() {
+                                Some(difficulty) => format!("CPU {}", difficulty.name()),
+                                None => "Human".to_string(),
+                            };
+                            parent
+                                .spawn((
+                                    ButtonBundle {
+                                        style: button_style.clone(),
+                                        ..default()
+                                    },
+                                    ButtonAction::CpuNext(player),
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section(
+                                        format!("P{}: {controller}", player + 1),
+                                        button_text_style.clone(),
+                                    ));
+                                });
+                            for action in Action::ALL {
+                                let key = if screen.waiting == Some((player, action)) {
+                                    "...".to_string()
+                                } else {
+                                    format!("{:?}", controls.key(action))
+                                };
+                                parent
+                                    .spawn((
+                                        ButtonBundle {
+                                            style: button_style.clone(),
+                                            ..default()
+                                        },
+                                        ButtonAction::Rebind(player, action),
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("P{} {}: {key}", player + 1, action.name()),
+                                            button_text_style.clone(),
+                                        ));
+                                    });
+                            }
+                        });
+                }
+            });
+
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: button_style,
+                        ..default()
+                    },
+                    ButtonAction::Controls,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Close", button_text_style));
+                });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::{
+        gamepad::{
+            GamepadAxisChangedEvent, GamepadButtonChangedEvent, GamepadConnection, GamepadEvent,
+            GamepadInfo,
+        },
+        InputPlugin,
+    };
+
+    use super::*;
+
+    // プレイヤーごとの動かしたい方向
+    #[derive(Resource, Default)]
+    struct Directions(Vec<Vec2>);
+
+    fn read_directions(
+        input: PlayerInput,
+        config: Res<KeyConfig>,
+        mut directions: ResMut<Directions>,
+    ) {
+        directions.0 = config
+            .players
+            .iter()
+            .enumerate()
+            .map(|(id, controls)| input.direction(controls, &Player { id }))
+            .collect();
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(InputPlugin)
+            .init_resource::<GamepadSlots>()
+            .init_resource::<Directions>()
+            .insert_resource(KeyConfig::default())
+            .add_systems(Update, (assign_gamepads, read_directions).chain());
+        app
+    }
+
+    fn send(app: &mut App, event: impl Into<GamepadEvent>) {
+        app.world.send_event(event.into());
+        app.update();
+    }
+
+    fn connect(app: &mut App, id: usize) {
+        let info = GamepadInfo {
+            name: format!("pad {id}"),
+        };
+        send(
+            app,
+            GamepadConnectionEvent::new(Gamepad::new(id), GamepadConnection::Connected(info)),
+        );
+    }
+
+    fn disconnect(app: &mut App, id: usize) {
+        send(
+            app,
+            GamepadConnectionEvent::new(Gamepad::new(id), GamepadConnection::Disconnected),
+        );
+    }
+
+    // 前から 3 人分の割り当て
+    fn slots(app: &App) -> Vec<Option<Gamepad>> {
+        let slots = app.world.resource::<GamepadSlots>();
+        (0..3).map(|player| slots.get(player)).collect()
+    }
+
+    fn direction(app: &App, player: usize) -> Vec2 {
+        app.world.resource::<Directions>().0[player]
+    }
+
+    fn is_diagonal(direction: Vec2) -> bool {
+        (direction - Vec2::ONE.normalize()).length() < 1e-5
+    }
+
+    #[test]
+    fn gamepads_are_assigned_in_connection_order() {
+        let mut app = app();
+        connect(&mut app, 3);
+        connect(&mut app, 1);
+        // 同じゲームパッドが二回つながっても増えない
+        connect(&mut app, 3);
+        assert_eq!(
+            slots(&app),
+            [Some(Gamepad::new(3)), Some(Gamepad::new(1)), None]
+        );
+
+        // 外れても他のプレイヤーはそのまま
+        disconnect(&mut app, 3);
+        assert_eq!(slots(&app), [None, Some(Gamepad::new(1)), None]);
+
+        // 次につながったものは空いている一番前に入る
+        connect(&mut app, 7);
+        connect(&mut app, 0);
+        assert_eq!(
+            slots(&app),
+            [
+                Some(Gamepad::new(7)),
+                Some(Gamepad::new(1)),
+                Some(Gamepad::new(0))
+            ]
+        );
+    }
+
+    #[test]
+    fn small_stick_input_is_ignored() {
+        let mut app = app();
+        connect(&mut app, 0);
+        let pad = Gamepad::new(0);
+
+        send(
+            &mut app,
+            GamepadAxisChangedEvent::new(pad, GamepadAxisType::LeftStickX, STICK_DEADZONE * 0.8),
+        );
+        assert_eq!(direction(&app, 0), Vec2::ZERO);
+
+        send(
+            &mut app,
+            GamepadAxisChangedEvent::new(pad, GamepadAxisType::LeftStickX, 0.5),
+        );
+        assert_eq!(direction(&app, 0), Vec2::new(0.5, 0.));
+        // 割り当てられていないプレイヤーは動かない
+        assert_eq!(direction(&app, 1), Vec2::ZERO);
+    }
+
+    #[test]
+    fn diagonal_input_is_clamped() {
+        let mut app = app();
+        connect(&mut app, 0);
+        connect(&mut app, 1);
+
+        // キーボードだけで斜め
+        let mut key = app.world.resource_mut::<Input<KeyCode>>();
+        key.press(KeyCode::W);
+        key.press(KeyCode::D);
+        app.update();
+        assert!(is_diagonal(direction(&app, 0)));
+
+        // 十字キーとスティックを一緒に使っても長さは 1 まで
+        let pad = Gamepad::new(1);
+        send(
+            &mut app,
+            GamepadButtonChangedEvent::new(pad, GamepadButtonType::DPadUp, 1.),
+        );
+        send(
+            &mut app,
+            GamepadAxisChangedEvent::new(pad, GamepadAxisType::LeftStickX, 1.),
+        );
+        assert!(is_diagonal(direction(&app, 1)));
+
+        // キーボードとゲームパッドは足し合わせてから長さを 1 までにする
+        send(
+            &mut app,
+            GamepadButtonChangedEvent::new(pad, GamepadButtonType::DPadUp, 0.),
+        );
+        assert_eq!(direction(&app, 1), Vec2::X);
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::Up);
+        app.update();
+        assert!(is_diagonal(direction(&app, 1)));
+    }
+}
@@ -0,0 +1,161 @@
+use bevy::prelude::*;
+
+use crate::{camera::player_layer, maze::Cell, Game, GridPos, Labyrinth, Player, RoundRules};
+
+// Radius で見えるマス数
+const FOG_RADIUS: i32 = 3;
+// Sight で見通せるマス数
+const SIGHT_RANGE: i32 = 8;
+
+// 一度見たマスを覆う霧の濃さ
+const EXPLORED_ALPHA: f32 = 0.6;
+
+// 霧を描く高さ (プレイヤーやアイテムより手前)
+pub const FOG_Z: f32 = 5.;
+
+// 見える範囲の決め方
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FogMode {
+    // 全て見える
+    #[default]
+    Off,
+    // 周りの決まったマス数だけ見える (壁の向こうも見える)
+    Radius,
+    // 壁に遮られずに見通せるマスだけ見える
+    Sight,
+}
+
+// プレイヤーごとの画面にだけ描く霧
+#[derive(Component)]
+pub struct FogTile {
+    player: usize,
+}
+
+// プレイヤーごとの一度見たマス
+#[derive(Resource, Default)]
+pub struct FogMemory(Vec<Vec<bool>>);
+
+impl FogMode {
+    const ALL: [FogMode; 3] = [FogMode::Off, FogMode::Radius, FogMode::Sight];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            FogMode::Off => "Off",
+            FogMode::Radius => "Radius",
+            FogMode::Sight => "Sight",
+        }
+    }
+
+    pub fn next(self) -> FogMode {
+        let index = Self::ALL.iter().position(|&m| m == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn prev(self) -> FogMode {
+        let index = Self::ALL.iter().position(|&m| m == self).unwrap_or(0);
+        Self::ALL[(index + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+
+    // from から cell が見えるか
+    fn can_see(self, labyrinth: &Labyrinth, from: IVec2, cell: IVec2) -> bool {
+        let distance = (cell - from).length_squared();
+        match self {
+            FogMode::Off => true,
+            FogMode::Radius => distance <= FOG_RADIUS * FOG_RADIUS,
+            FogMode::Sight => {
+                distance <= SIGHT_RANGE * SIGHT_RANGE
+                    && labyrinth.0.can_see(Cell::from(from), Cell::from(cell))
+            }
+        }
+    }
+}
+
+// 迷路を作り直したら霧も作り直す
+pub fn spawn_fog(
+    mut commands: Commands,
+    fog_query: Query<Entity, With<FogTile>>,
+    mut memory: ResMut<FogMemory>,
+    labyrinth: Res<Labyrinth>,
+    game: Res<Game>,
+    rules: Res<RoundRules>,
+) {
+    for entity in &fog_query {
+        commands.entity(entity).despawn();
+    }
+
+    let cells = (game.maze_width() * game.maze_height()) as usize;
+    memory.0 = vec![vec![false; cells]; rules.player_num];
+    if rules.fog == FogMode::Off {
+        return;
+    }
+
+    for player in 0..rules.player_num {
+        for cell in labyrinth.0.cells() {
+            commands.spawn((
+                SpriteBundle {
+                    transform: Transform {
+                        translation: Vec3::new(0., 0., FOG_Z),
+                        scale: game.block_size.extend(0.0),
+                        ..default()
+                    },
+                    sprite: Sprite {
+                        color: Color::BLACK,
+                        ..default()
+                    },
+                    ..default()
+                },
+                GridPos(cell.into()),
+                player_layer(player),
+                FogTile { player },
+            ));
+        }
+    }
+}
+
+// 見えているマスの霧を消し、一度見たマスは薄く残す
+pub fn update_fog(
+    player_query: Query<(&Player, &GridPos)>,
+    mut fog_query: Query<(&FogTile, &GridPos, &mut Sprite)>,
+    mut memory: ResMut<FogMemory>,
+    labyrinth: Res<Labyrinth>,
+    game: Res<Game>,
+    rules: Res<RoundRules>,
+) {
+    if rules.fog == FogMode::Off {
+        return;
+    }
+    let positions: Vec<Option<IVec2>> = (0..rules.player_num)
+        .map(|id| {
+            player_query
+                .iter()
+                .find(|(player, _)| player.id == id)
+                .map(|(_, pos)| pos.0)
+        })
+        .collect();
+    let width = game.maze_width();
+
+    for (tile, pos, mut sprite) in &mut fog_query {
+        let Some(&Some(from)) = positions.get(tile.player) else {
+            continue;
+        };
+        let Some(explored) = memory
+            .0
+            .get_mut(tile.player)
+            .and_then(|explored| explored.get_mut((pos.0.y * width + pos.0.x) as usize))
+        else {
+            continue;
+        };
+
+        let alpha = if rules.fog.can_see(&labyrinth, from, pos.0) {
+            *explored = true;
+            0.
+        } else if *explored {
+            EXPLORED_ALPHA
+        } else {
+            1.
+        };
+        if sprite.color.a() != alpha {
+            sprite.color.set_a(alpha);
+        }
+    }
+}
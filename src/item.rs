@@ -0,0 +1,454 @@
+use bevy::prelude::*;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    camera::player_layer,
+    config::ConfigFile,
+    fog::{FogMode, FOG_Z},
+    maze::Cell,
+    Game, GameRng, Goal, GridPos, ItemPosition, Labyrinth, Player, RoundRules, ScoreBoard,
+    ScoreEvent,
+};
+
+// 効果が続く時間 (秒)
+const SPEED_BOOST_TIME: f32 = 5.;
+const WALL_PASS_TIME: f32 = 3.;
+const FREEZE_TIME: f32 = 2.;
+const REVEAL_TIME: f32 = 3.;
+
+// スピードアップ中の速さの倍率
+const SPEED_BOOST_RATE: f32 = 1.6;
+
+// 霧がある時の経路の印の大きさ (マスに対する割合)
+const PATH_MARK_RATE: f32 = 0.2;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Item {
+    // 値の分だけ点が入る
+    Point(usize),
+    // しばらく速く動ける
+    SpeedBoost,
+    // しばらく壁をすり抜けられる
+    WallPass,
+    // 相手をしばらく止める
+    Freeze,
+    // 一番近いアイテムまでの道をしばらく表示する
+    Reveal,
+}
+
+#[derive(Bundle)]
+pub struct ItemBundle {
+    sprite_bundle: SpriteBundle,
+    grid_pos: GridPos,
+    item: Item,
+}
+
+// アイテムの出やすさと出し方
+#[derive(Resource)]
+pub struct SpawnTable {
+    // 種類ごとの重み
+    pub weights: Vec<(Item, u32)>,
+    // プレイヤーからこのマス数 (道のり) 以内には出さない
+    pub min_player_distance: u32,
+}
+
+// このラウンドで出したアイテムの数と、次に出すまでの時間
+#[derive(Resource, Default)]
+pub struct ItemSpawner {
+    pub spawned: usize,
+    cooldown: f32,
+    // まだ使っていないアイテムの位置 (ItemPosition の順番のまま)
+    rest: Vec<IVec2>,
+}
+
+// 霧がある時に経路を描く印 (そのプレイヤーの画面にだけ映る)
+#[derive(Component)]
+pub struct PathMark;
+
+// プレイヤーにかかっている効果の残り時間 (秒)
+#[derive(Component, Debug, Default)]
+pub struct Effects {
+    pub speed_boost: f32,
+    pub wall_pass: f32,
+    pub frozen: f32,
+    pub reveal: f32,
+}
+
+impl ItemBundle {
+    // 新しいアイテムを作る
+    pub fn new(item: Item, pos: IVec2, size: Vec2) -> ItemBundle {
+        ItemBundle {
+            sprite_bundle: SpriteBundle {
+                transform: Transform {
+                    scale: size.extend(0.0),
+                    ..default()
+                },
+                sprite: Sprite {
+                    color: item.color(),
+                    ..default()
+                },
+                ..default()
+            },
+            grid_pos: GridPos(pos),
+            item,
+        }
+    }
+}
+
+impl Default for SpawnTable {
+    fn default() -> Self {
+        SpawnTable {
+            weights: vec![
+                (Item::Point(1), 50),
+                (Item::Point(3), 15),
+                (Item::Point(5), 5),
+                (Item::SpeedBoost, 10),
+                (Item::WallPass, 7),
+                (Item::Freeze, 8),
+                (Item::Reveal, 5),
+            ],
+            min_player_distance: 4,
+        }
+    }
+}
+
+// アイテムの出やすさの設定ファイル ("point3 = 15" や "min_player_distance = 4" の形の行)
+// 初期設定に無い点数のアイテムも "point10 = 1" のように足せる
+impl ConfigFile for SpawnTable {
+    const PATH: &'static str = "items.cfg";
+
+    fn apply(&mut self, name: &str, value: &str) -> bool {
+        let Ok(value) = value.parse::<u32>() else {
+            return false;
+        };
+        if name == "min_player_distance" {
+            self.min_player_distance = value;
+            return true;
+        }
+
+        let Some(item) = Item::from_name(name) else {
+            return false;
+        };
+        match self.weights.iter_mut().find(|(i, _)| *i == item) {
+            Some((_, weight)) => *weight = value,
+            None => self.weights.push((item, value)),
+        }
+        true
+    }
+}
+
+impl SpawnTable {
+    // 重みに従ってアイテムの種類を選ぶ (重みが全て 0 なら 1 点のアイテム)
+    pub fn random<R: Rng + ?Sized>(&self, rng: &mut R) -> Item {
+        self.weights
+            .choose_weighted(rng, |&(_, weight)| weight)
+            .map_or(Item::Point(1), |&(item, _)| item)
+    }
+}
+
+impl ItemSpawner {
+    // このラウンドのアイテムを全て出し終えたか
+    pub fn is_done(&self, rules: &RoundRules) -> bool {
+        self.spawned >= rules.item_num
+    }
+}
+
+impl Item {
+    // 設定ファイルでの名前から作る
+    fn from_name(name: &str) -> Option<Item> {
+        match name {
+            "speed_boost" => Some(Item::SpeedBoost),
+            "wall_pass" => Some(Item::WallPass),
+            "freeze" => Some(Item::Freeze),
+            "reveal" => Some(Item::Reveal),
+            _ => name.strip_prefix("point")?.parse().ok().map(Item::Point),
+        }
+    }
+
+    pub const fn color(&self) -> Color {
+        match self {
+            Item::Point(1) => Color::BLUE,
+            Item::Point(3) => Color::VIOLET,
+            Item::Point(_) => Color::CYAN,
+            Item::SpeedBoost => Color::ORANGE,
+            Item::WallPass => Color::GRAY,
+            Item::Freeze => Color::ALICE_BLUE,
+            Item::Reveal => Color::PINK,
+        }
+    }
+}
+
+impl Effects {
+    pub fn speed_rate(&self) -> f32 {
+        if self.speed_boost > 0. {
+            SPEED_BOOST_RATE
+        } else {
+            1.
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen > 0.
+    }
+
+    pub fn can_pass_wall(&self) -> bool {
+        self.wall_pass > 0.
+    }
+}
+
+// アイテムを作る
+// 一度に置くのは max_items_on_field 個までで、残りは取られてから出てくる
+pub fn create_item(
+    mut commands: Commands,
+    item_position: Res<ItemPosition>,
+    game: Res<Game>,
+    rules: Res<RoundRules>,
+    table: Res<SpawnTable>,
+    mut spawner: ResMut<ItemSpawner>,
+    mut rng: ResMut<GameRng>,
+) {
+    let count = rules.initial_item_num().min(item_position.0.len());
+    let (positions, rest) = item_position.0.split_at(count);
+
+    // 組にしたマス (点対称な位置や道のりが逆の位置) には同じ種類のアイテムを置く
+    for group in positions.chunks(rules.item_group) {
+        let item = table.random(&mut rng.0);
+        for &pos in group {
+            commands.spawn(ItemBundle::new(item, pos, game.player_size));
+        }
+    }
+
+    *spawner = ItemSpawner {
+        spawned: positions.len(),
+        cooldown: game.respawn_delay,
+        rest: rest.to_vec(),
+    };
+}
+
+// アイテムが減ったら、しばらくしてから空いているマスに出す
+// マスは ItemPosition の続きから選ぶので、最初と同じ置き方 (釣り合い、点対称) になる
+// プレイヤーのすぐ近くには出さない
+pub fn respawn_item(
+    mut commands: Commands,
+    item_query: Query<&GridPos, With<Item>>,
+    player_query: Query<&GridPos, With<Player>>,
+    labyrinth: Res<Labyrinth>,
+    game: Res<Game>,
+    rules: Res<RoundRules>,
+    table: Res<SpawnTable>,
+    mut spawner: ResMut<ItemSpawner>,
+    mut rng: ResMut<GameRng>,
+    time: Res<Time>,
+) {
+    let group = rules.item_group;
+    let on_field = item_query.iter().count();
+    let full = rules.max_items_on_field != 0 && on_field + group > rules.max_items_on_field;
+    if spawner.is_done(&rules) || full {
+        return;
+    }
+
+    spawner.cooldown -= time.delta_seconds();
+    if spawner.cooldown > 0. {
+        return;
+    }
+
+    let occupied: Vec<IVec2> = item_query
+        .iter()
+        .chain(&player_query)
+        .map(|pos| pos.0)
+        .collect();
+    let distances: Vec<_> = player_query
+        .iter()
+        .map(|pos| labyrinth.0.distances_from(pos.0.into()))
+        .collect();
+
+    let is_free = |pos: &IVec2| !occupied.contains(pos);
+    let is_far = |pos: &IVec2| {
+        distances.iter().all(|distance| {
+            distance
+                .get(Cell::from(*pos))
+                .is_none_or(|d| d > table.min_player_distance)
+        })
+    };
+
+    // 遠いマスが無ければ空いているマスならどこでもいい
+    let groups = || spawner.rest.chunks(group).enumerate();
+    let found = groups()
+        .find(|(_, cells)| cells.iter().all(|pos| is_free(pos) && is_far(pos)))
+        .or_else(|| groups().find(|(_, cells)| cells.iter().all(is_free)))
+        .map(|(index, _)| index * group);
+    let Some(start) = found else {
+        return;
+    };
+
+    let item = table.random(&mut rng.0);
+    let end = (start + group).min(spawner.rest.len());
+    let cells: Vec<IVec2> = spawner.rest.drain(start..end).collect();
+    for &pos in &cells {
+        commands.spawn(ItemBundle::new(item, pos, game.player_size));
+    }
+    spawner.spawned += cells.len();
+    spawner.cooldown = game.respawn_delay;
+}
+
+// 効果の残り時間を減らす
+pub fn effects_tick(mut query: Query<&mut Effects>, time: Res<Time>) {
+    let delta = time.delta_seconds();
+    for mut effects in &mut query {
+        let effects = &mut *effects;
+        for remaining in [
+            &mut effects.speed_boost,
+            &mut effects.wall_pass,
+            &mut effects.frozen,
+            &mut effects.reveal,
+        ] {
+            *remaining = (*remaining - delta).max(0.);
+        }
+    }
+}
+
+// アイテムの判定
+// プレイヤーと同じマスにあるアイテムを取り、種類ごとの効果を与える
+// 同じマスに何人もいる時は全員が取ったことにする (id で有利不利が出ないように)
+pub fn item_collision(
+    mut commands: Commands,
+    mut player_query: Query<(&Player, &GridPos, &mut Effects)>,
+    item_query: Query<(Entity, &GridPos, &Item)>,
+    mut score_board: ResMut<ScoreBoard>,
+    mut event: EventWriter<ScoreEvent>,
+) {
+    for (entity, pos, item) in &item_query {
+        let takers: Vec<Player> = player_query
+            .iter()
+            .filter(|&(_, player_pos, _)| player_pos == pos)
+            .map(|(player, _, _)| *player)
+            .collect();
+        if takers.is_empty() {
+            continue;
+        }
+
+        for (player, _, mut effects) in &mut player_query {
+            let own = takers.contains(player);
+            match *item {
+                Item::Point(value) if own => {
+                    score_board.add(player.id, value);
+                    event.send_default();
+                }
+                Item::SpeedBoost if own => effects.speed_boost = SPEED_BOOST_TIME,
+                Item::WallPass if own => effects.wall_pass = WALL_PASS_TIME,
+                Item::Reveal if own => effects.reveal = REVEAL_TIME,
+                // 取ったプレイヤー以外を止める
+                Item::Freeze if !own => effects.frozen = FREEZE_TIME,
+                _ => (),
+            }
+        }
+
+        commands.entity(entity).despawn();
+    }
+}
+
+// 一番近いアイテム (またはゴール) までの最短経路を表示する
+// Reveal の効果中か、設定で経路表示をオンにしている時
+// ギズモは全員の画面に映るので、霧がある時はそのプレイヤーの画面にだけ印を置く
+pub fn reveal_path(
+    mut commands: Commands,
+    mut gizmos: Gizmos,
+    player_query: Query<(&Player, &GridPos, &Effects, &Sprite)>,
+    target_query: Query<&GridPos, Or<(With<Item>, With<Goal>)>>,
+    mark_query: Query<Entity, With<PathMark>>,
+    labyrinth: Res<Labyrinth>,
+    game: Res<Game>,
+    rules: Res<RoundRules>,
+) {
+    for entity in &mark_query {
+        commands.entity(entity).despawn();
+    }
+    let targets: Vec<_> = target_query.iter().map(|pos| pos.0.into()).collect();
+
+    for (player, pos, effects, sprite) in &player_query {
+        if effects.reveal <= 0. && !game.show_solver {
+            continue;
+        }
+        let Some(path) = labyrinth.0.path_to_nearest(pos.0.into(), &targets) else {
+            continue;
+        };
+
+        if rules.fog == FogMode::Off {
+            gizmos.linestrip_2d(
+                path.into_iter().map(|cell| game.grid_to_world(cell.into())),
+                sprite.color,
+            );
+            continue;
+        }
+        for cell in path {
+            commands.spawn((
+                SpriteBundle {
+                    transform: Transform {
+                        // 霧より手前
+                        translation: game.grid_to_world(cell.into()).extend(FOG_Z + 1.),
+                        scale: (game.block_size * PATH_MARK_RATE).extend(0.0),
+                        ..default()
+                    },
+                    sprite: Sprite {
+                        color: sprite.color,
+                        ..default()
+                    },
+                    ..default()
+                },
+                player_layer(player.id),
+                PathMark,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weight(table: &SpawnTable, item: Item) -> Option<u32> {
+        table
+            .weights
+            .iter()
+            .find(|&&(i, _)| i == item)
+            .map(|&(_, weight)| weight)
+    }
+
+    #[test]
+    fn spawn_table_reads_weights_and_distance() {
+        let mut table = SpawnTable::default();
+        table.parse(
+            "# コメント\n\
+             \n\
+             freeze = 0\n  \
+             point3=20  \n\
+             point10 = 2\n\
+             min_player_distance = 6\n",
+        );
+
+        assert_eq!(weight(&table, Item::Freeze), Some(0));
+        assert_eq!(weight(&table, Item::Point(3)), Some(20));
+        // 初期設定に無い点数は足す
+        assert_eq!(weight(&table, Item::Point(10)), Some(2));
+        assert_eq!(table.min_player_distance, 6);
+        // 書いていないものは初期設定のまま
+        assert_eq!(weight(&table, Item::Point(1)), Some(50));
+    }
+
+    #[test]
+    fn spawn_table_skips_unreadable_lines() {
+        let mut table = SpawnTable::default();
+        table.parse(
+            "bomb = 5\n\
+             reveal = many\n\
+             speed_boost = -1\n\
+             min_player_distance =\n\
+             pointx = 3\n\
+             wall_pass 7\n",
+        );
+
+        let default = SpawnTable::default();
+        assert_eq!(table.weights, default.weights);
+        assert_eq!(table.min_player_distance, default.min_player_distance);
+    }
+}
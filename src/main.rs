@@ -1,28 +1,51 @@
-use bevy::{
-    prelude::*,
-    sprite::collide_aabb::{collide, Collision},
-};
-use rand::{seq::SliceRandom, thread_rng};
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
+mod ai;
+mod camera;
+mod config;
+mod controls;
+mod fog;
+mod item;
+mod maze;
+mod movement;
+mod round;
+mod sim;
+
+use std::collections::BTreeMap;
+
+use ai::Bot;
+use bevy::{prelude::*, transform::TransformSystem};
+use config::ConfigFile;
+use controls::{Controls, GamepadSlots, KeyConfig, PlayerInput};
+use fog::FogMode;
+use item::{Effects, Item};
+use maze::{Algorithm, Cell, Maze};
+use movement::{GridMove, Movement};
+use rand::{random, rngs::StdRng, seq::SliceRandom, SeedableRng};
 
 // ブロックの数
-const BLOCK_NUM: f32 = 11.;
-
-const BLOCK_SIZE: Vec2 = Vec2::new(600. / (BLOCK_NUM + 2.), 600. / (BLOCK_NUM + 2.));
-// プレイヤーの初期位置
-const PLAYER1_INITIAL_POSITION: Vec2 = Vec2::new(
-    -BLOCK_SIZE.x * (BLOCK_NUM + 1.) / 2.,
-    BLOCK_SIZE.y * (BLOCK_NUM + 1.) / 2.,
-);
-const PLAYER2_INITIAL_POSITION: Vec2 = Vec2::new(
-    BLOCK_SIZE.x * (BLOCK_NUM + 1.) / 2.,
-    -BLOCK_SIZE.y * (BLOCK_NUM + 1.) / 2.,
-);
-
-const PLAYER_SIZE: Vec2 = Vec2::new(BLOCK_SIZE.x / 2.0, BLOCK_SIZE.y / 2.0);
-const PLAYER_SPEED: f32 = 4000. / BLOCK_NUM;
+const BLOCK_NUM_X: i32 = 11;
+const BLOCK_NUM_Y: i32 = 11;
 
 const ITEM_NUM: usize = 7;
 
+const ROUNDS: usize = 3;
+
+// 脱出レースで公平なゴールを探して迷路を作る回数
+const FAIR_GOAL_ATTEMPTS: usize = 200;
+
+// 一つの迷路で遊べる人数
+const MIN_PLAYERS: usize = 2;
+const MAX_PLAYERS: usize = 4;
+
+// プレイヤーの色 (id 順)
+const PLAYER_COLORS: [Color; MAX_PLAYERS] =
+    [Color::RED, Color::GREEN, Color::YELLOW, Color::FUCHSIA];
+
+// 制限時間の設定を一回で変える秒数
+const TIME_LIMIT_STEP: u32 = 30;
+const RESPAWN_DELAY_STEP: f32 = 1.;
+
 const SCOREBOARD_TEXT_PADDING: Val = Val::Px(8.0);
 const SCOREBOARD_FONT_SIZE: f32 = 40.0;
 
@@ -33,24 +56,92 @@ enum Status {
     #[default]
     Shuffle,
     Disabled,
+    RoundOver,
 }
 
 #[derive(Component, Clone, Copy)]
 enum ButtonAction {
     Shuffle,
+    NextRound,
+    NewMatch,
     ItemNumUp,
     ItemNumDown,
-    BlockNumUp,
-    BlockNumDown,
+    WidthUp,
+    WidthDown,
+    HeightUp,
+    HeightDown,
+    AlgorithmNext,
+    AlgorithmPrev,
+    BraidUp,
+    BraidDown,
+    RoundsUp,
+    RoundsDown,
+    TimeLimitUp,
+    TimeLimitDown,
+    OnFieldUp,
+    OnFieldDown,
+    PlacementNext,
+    PlacementPrev,
+    SymmetricToggle,
+    RespawnUp,
+    RespawnDown,
+    ModeNext,
+    ModePrev,
+    PlayersUp,
+    PlayersDown,
+    MovementNext,
+    MovementPrev,
+    SolverToggle,
+    FogNext,
+    FogPrev,
+    SplitToggle,
+    // 操作設定の画面を開く・閉じる
+    Controls,
+    // (プレイヤー, 操作) のキーを変える
+    Rebind(usize, controls::Action),
+    // プレイヤーを人とコンピュータで切り替える
+    CpuNext(usize),
 }
 
-#[derive(Component)]
-enum ScoreboardSection {
-    P1,
-    P2,
+// 勝ち方
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum GameMode {
+    // アイテムを多く集めた方の勝ち
+    #[default]
+    Items,
+    // 先にゴールに着いた方の勝ち
+    ExitRace,
+}
+
+// アイテムの置き方
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ItemPlacement {
+    // ランダムに置く
+    #[default]
+    Random,
+    // 道のりが逆になるマスと二つずつ組にして、全員からの道のりの合計を揃える
+    Balanced,
 }
 
+// どのプレイヤーのスコアを表示するか
+#[derive(Component)]
+struct ScoreboardSection(usize);
+
 fn main() {
+    // `--simulate <ラウンド数>` ならウィンドウを出さずにコンピュータ同士で遊ばせる
+    let args: Vec<String> = std::env::args().collect();
+    match sim::SimConfig::from_args(&args) {
+        Ok(Some(config)) => {
+            sim::run(config);
+            return;
+        }
+        Ok(None) => (),
+        Err(message) => {
+            eprintln!("{message}");
+            std::process::exit(2);
+        }
+    }
+
     App::new()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
@@ -60,197 +151,594 @@ fn main() {
             }),
             ..default()
         }))
-        .add_state::<Status>()
-        .add_event::<ScoreEvent>()
-        .init_resource::<ScoreBoard>()
-        .init_resource::<ItemPosition>()
-        .init_resource::<Game>()
-        .add_systems(Startup, (setup, labyrinth_setup))
-        .add_systems(OnEnter(Status::Shuffle), labyrinth_setup)
-        .add_systems(OnExit(Status::Shuffle), create_item)
-        .add_systems(
-            Update,
-            (
-                move_player1,
-                move_player2,
-                wall_collision,
-                item_collision::<Player1>,
-                item_collision::<Player2>,
-            )
-                .run_if(in_state(Status::Disabled)),
-        )
-        .add_systems(Update, (button_system, score_board_update))
+        .add_plugins((GamePlugin, ScreenPlugin))
         .run();
 }
 
+// ゲームを進めるのに必要なもの (描画しなくても動く)
+struct GamePlugin;
+
+// 画面の表示とボタン
+struct ScreenPlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<Status>()
+            .add_event::<ScoreEvent>()
+            .init_resource::<ScoreBoard>()
+            .init_resource::<round::MatchScore>()
+            .init_resource::<round::RoundTimer>()
+            .init_resource::<ItemPosition>()
+            .init_resource::<RoundRules>()
+            .init_resource::<Labyrinth>()
+            .init_resource::<GameRng>()
+            .insert_resource(item::SpawnTable::load())
+            .init_resource::<item::ItemSpawner>()
+            .init_resource::<Game>()
+            .init_resource::<controls::ControlsScreen>()
+            .init_resource::<GamepadSlots>()
+            .insert_resource(KeyConfig::load())
+            .insert_resource(MazeSeed::from_args())
+            .add_systems(Startup, labyrinth_setup)
+            .add_systems(
+                OnEnter(Status::Shuffle),
+                (
+                    labyrinth_setup,
+                    round::reset_score_board,
+                    round::reset_round_timer,
+                ),
+            )
+            .add_systems(
+                OnExit(Status::Shuffle),
+                item::create_item.run_if(in_mode(GameMode::Items)),
+            )
+            .add_systems(
+                Update,
+                (
+                    ai::bot_think,
+                    move_player
+                        .run_if(controls::screen_closed)
+                        .run_if(in_movement(Movement::Free)),
+                    movement::move_player_grid
+                        .run_if(controls::screen_closed)
+                        .run_if(in_movement(Movement::Grid)),
+                    wall_collision.run_if(in_movement(Movement::Free)),
+                    player_grid_pos_update,
+                    item::item_collision,
+                    // 出したばかりのアイテムはまだ見えないので、終了判定を先にする
+                    round::round_end_check.run_if(in_mode(GameMode::Items)),
+                    item::respawn_item.run_if(in_mode(GameMode::Items)),
+                    round::goal_check.run_if(in_mode(GameMode::ExitRace)),
+                    round::round_timer_tick,
+                )
+                    .chain()
+                    .run_if(in_state(Status::Disabled)),
+            )
+            .add_systems(OnEnter(Status::RoundOver), round::record_round_result)
+            .add_systems(
+                Update,
+                (
+                    item::effects_tick.run_if(in_state(Status::Disabled)),
+                    controls::assign_gamepads,
+                    ai::sync_bots,
+                ),
+            )
+            .add_systems(PostUpdate, grid_transform_update);
+    }
+}
+
+impl Plugin for ScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<fog::FogMemory>()
+            .add_systems(Startup, (setup, round::setup_match_text))
+            .add_systems(
+                OnExit(Status::Shuffle),
+                (fog::spawn_fog, camera::setup_cameras),
+            )
+            .add_systems(
+                OnEnter(Status::RoundOver),
+                round::spawn_result_screen.after(round::record_round_result),
+            )
+            .add_systems(OnExit(Status::RoundOver), round::despawn_result_screen)
+            .add_systems(
+                Update,
+                (
+                    item::reveal_path,
+                    button_system,
+                    score_board_update,
+                    seed_text_update,
+                    round::match_text_update,
+                    round::timer_text_update,
+                    controls::rebind_key,
+                    controls::controls_screen_update,
+                    fog::update_fog,
+                    camera::layout_cameras,
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                camera::follow_players.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
 #[derive(Resource)]
 struct Game {
+    // ラウンドで出るアイテムの数
     item_num: usize,
-    block_num: f32,
+    // 同時に置かれるアイテムの最大数 (0 なら制限なし)
+    max_items_on_field: usize,
+    // アイテムが取られてから次が出るまでの時間 (秒)
+    respawn_delay: f32,
+    placement: ItemPlacement,
+    block_num_x: i32,
+    block_num_y: i32,
+    // 今のラウンドの迷路のマス数 (外周の壁を含む)
+    // ブロックの数はラウンドの途中でも変えられるので、init の時に決める
+    maze_size: IVec2,
     block_size: Vec2,
     player_size: Vec2,
     player_speed: f32,
-    player1_init_pos: Vec2,
-    player2_init_pos: Vec2,
+    movement: Movement,
+    player_num: usize,
+    // コンピュータが動かすプレイヤーの強さ (None なら人)
+    cpu: [Option<ai::Difficulty>; MAX_PLAYERS],
+    // プレイヤーの初期位置 (id 順)
+    player_init_pos: Vec<IVec2>,
+    algorithm: Algorithm,
+    // 行き止まりを壊す割合 (0.0 ~ 1.0)
+    braid: f32,
+    // 点対称の迷路にするか
+    symmetric: bool,
+    // 全員の最短経路を表示するか (練習や観戦用)
+    show_solver: bool,
+    // 霧で見える範囲を狭めるか (画面はプレイヤーごとに分かれる)
+    fog: FogMode,
+    // プレイヤーごとの画面でそれぞれを追いかけるか (大きい迷路用)
+    split_screen: bool,
+    // 試合のラウンド数 (best of N)
+    rounds: usize,
+    // ラウンドの制限時間 (秒、0 なら制限なし)
+    time_limit: u32,
+    mode: GameMode,
 }
 
 #[derive(Resource, Default)]
 struct ScoreBoard {
-    player1: usize,
-    player2: usize,
+    // プレイヤーの id ごとのスコア
+    scores: BTreeMap<usize, usize>,
 }
 
 #[derive(Event, Default)]
 struct ScoreEvent;
 
-#[derive(Component)]
-struct Player1;
+// 何番目のプレイヤーか (0 から)
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+struct Player {
+    id: usize,
+}
 
 #[derive(Component)]
-struct Player2;
+struct Block;
 
+// 脱出レースのゴール
 #[derive(Component)]
-struct Block;
+struct Goal;
 
+// 迷路上のマスの座標
+// ワールド座標は描画するときにだけ計算する
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct GridPos(IVec2);
+
+// アイテムを置けるマス
 #[derive(Resource, Default)]
-struct ItemPosition(Vec<Vec2>);
+struct ItemPosition(Vec<IVec2>);
 
-#[derive(Component, Debug)]
-enum Item {
-    Item,
+// 今のラウンドの決まり (ラウンドの途中で設定を変えても次のラウンドから)
+#[derive(Resource, Default)]
+struct RoundRules {
+    mode: GameMode,
+    // ラウンドに出ているプレイヤーの数
+    player_num: usize,
+    fog: FogMode,
+    split_screen: bool,
+    // 一緒に出すアイテムの数
+    item_group: usize,
+    // ラウンドで出るアイテムの数
+    item_num: usize,
+    // 同時に置かれるアイテムの最大数 (0 なら制限なし)
+    max_items_on_field: usize,
 }
 
-#[derive(Bundle)]
-struct ItemBundle {
-    sprite_bundle: SpriteBundle,
-    item: Item,
-}
+// 迷路とアイテムの配置を決めるシード値
+#[derive(Resource)]
+struct MazeSeed(u64);
+
+#[derive(Component)]
+struct SeedText;
+
+// シード値から作る乱数 (迷路を作った後もアイテムの種類などに使う)
+#[derive(Resource)]
+struct GameRng(StdRng);
+
+// 現在の迷路
+#[derive(Resource, Default)]
+struct Labyrinth(Maze);
 
 #[derive(Bundle)]
 struct BlockBundle {
     sprite_bundle: SpriteBundle,
+    grid_pos: GridPos,
     block: Block,
 }
 
 impl BlockBundle {
     // 新しいブロックを作る
-    fn new(translation: Vec2, size: Vec2) -> BlockBundle {
+    fn new(pos: IVec2, size: Vec2) -> BlockBundle {
         BlockBundle {
             sprite_bundle: SpriteBundle {
                 transform: Transform {
-                    translation: translation.extend(0.0),
                     scale: size.extend(0.0),
                     ..default()
                 },
                 ..default()
             },
+            grid_pos: GridPos(pos),
             block: Block,
         }
     }
 }
 
-impl ItemBundle {
-    // 新しいアイテムを作る
-    fn new(item: Item, translation: Vec2, size: Vec2) -> ItemBundle {
-        ItemBundle {
-            sprite_bundle: SpriteBundle {
-                transform: Transform {
-                    translation: translation.extend(0.0),
-                    scale: size.extend(0.0),
-                    ..default()
-                },
-                sprite: Sprite {
-                    color: item.color(),
-                    ..default()
-                },
-                ..default()
-            },
-            item,
+impl GameMode {
+    const fn name(self) -> &'static str {
+        match self {
+            GameMode::Items => "Items",
+            GameMode::ExitRace => "Exit",
+        }
+    }
+
+    const fn toggle(self) -> GameMode {
+        match self {
+            GameMode::Items => GameMode::ExitRace,
+            GameMode::ExitRace => GameMode::Items,
         }
     }
 }
 
-impl Item {
-    const fn color(&self) -> Color {
+impl ItemPlacement {
+    const fn name(self) -> &'static str {
+        match self {
+            ItemPlacement::Random => "Random",
+            ItemPlacement::Balanced => "Balanced",
+        }
+    }
+
+    const fn toggle(self) -> ItemPlacement {
         match self {
-            Item::Item => Color::BLUE,
+            ItemPlacement::Random => ItemPlacement::Balanced,
+            ItemPlacement::Balanced => ItemPlacement::Random,
         }
     }
 }
 
+// 今のラウンドの勝ち方が mode の時だけ動かす
+fn in_mode(mode: GameMode) -> impl Fn(Res<RoundRules>) -> bool {
+    move |rules: Res<RoundRules>| rules.mode == mode
+}
+
+fn in_movement(movement: Movement) -> impl Fn(Res<Game>) -> bool {
+    move |game: Res<Game>| game.movement == movement
+}
+
+impl RoundRules {
+    // ラウンドの最初に置くアイテムの数
+    fn initial_item_num(&self) -> usize {
+        match self.max_items_on_field {
+            0 => self.item_num,
+            max => self.item_num.min(max),
+        }
+    }
+}
+
+impl ScoreBoard {
+    fn get(&self, id: usize) -> usize {
+        self.scores.get(&id).copied().unwrap_or(0)
+    }
+
+    fn add(&mut self, id: usize, points: usize) {
+        *self.scores.entry(id).or_default() += points;
+    }
+}
+
+impl From<IVec2> for Cell {
+    fn from(pos: IVec2) -> Cell {
+        Cell::new(pos.x, pos.y)
+    }
+}
+
+impl From<Cell> for IVec2 {
+    fn from(cell: Cell) -> IVec2 {
+        IVec2::new(cell.x, cell.y)
+    }
+}
+
 impl Default for Game {
     fn default() -> Self {
-        Game {
+        let mut game = Game {
             item_num: ITEM_NUM,
-            block_num: BLOCK_NUM,
-            block_size: BLOCK_SIZE,
-            player_size: PLAYER_SIZE,
-            player_speed: PLAYER_SPEED,
-            player1_init_pos: PLAYER1_INITIAL_POSITION,
-            player2_init_pos: PLAYER2_INITIAL_POSITION,
+            max_items_on_field: 0,
+            respawn_delay: RESPAWN_DELAY_STEP * 2.,
+            placement: ItemPlacement::default(),
+            block_num_x: BLOCK_NUM_X,
+            block_num_y: BLOCK_NUM_Y,
+            maze_size: IVec2::ZERO,
+            block_size: Vec2::ZERO,
+            player_size: Vec2::ZERO,
+            player_speed: 0.,
+            movement: Movement::default(),
+            player_num: MIN_PLAYERS,
+            cpu: [None; MAX_PLAYERS],
+            player_init_pos: Vec::new(),
+            algorithm: Algorithm::default(),
+            braid: 0.,
+            symmetric: false,
+            show_solver: false,
+            fog: FogMode::default(),
+            split_screen: false,
+            rounds: ROUNDS,
+            time_limit: 0,
+            mode: GameMode::default(),
+        };
+        // ブロックの大きさなどはブロックの数から計算する
+        game.init();
+        game
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng(StdRng::from_entropy())
+    }
+}
+
+impl MazeSeed {
+    // `--seed <数値>` が指定されていればそれを使う
+    fn from_args() -> MazeSeed {
+        let mut args = std::env::args().skip_while(|arg| arg != "--seed").skip(1);
+        match args.next().and_then(|seed| seed.parse().ok()) {
+            Some(seed) => MazeSeed(seed),
+            None => MazeSeed(random()),
         }
     }
+
+    // 新しいシード値にする
+    fn reroll(&mut self) {
+        self.0 = random();
+    }
+
+    fn rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.0)
+    }
 }
 
 impl Game {
-    const WALL_MAX: f32 = 600.0;
+    const WALL_MAX_X: f32 = 800.0;
+    const WALL_MAX_Y: f32 = 600.0;
 
     fn init(&mut self) {
-        if self.block_num < 1. {
-            self.block_num = 1.;
+        self.block_num_x = self.block_num_x.max(1);
+        self.block_num_y = self.block_num_y.max(1);
+
+        self.maze_size = IVec2::new(self.block_num_x + 4, self.block_num_y + 4);
+
+        let block_num_x = self.block_num_x as f32;
+        let block_num_y = self.block_num_y as f32;
+
+        // ブロックは正方形にして、縦横どちらもはみ出ない大きさにする
+        let block =
+            (Self::WALL_MAX_X / (block_num_x + 2.0)).min(Self::WALL_MAX_Y / (block_num_y + 2.0));
+
+        self.block_size = Vec2::new(block, block);
+        self.player_size = Vec2::new(block / 2.0, block / 2.0);
+        self.player_speed = ((4000. / block_num_x) + (4000. / block_num_y)) / 2.;
+        self.player_num = self.player_num.clamp(MIN_PLAYERS, MAX_PLAYERS);
+        // プレイヤーの初期位置は四隅のマス
+        // 1 人目と 2 人目は左上と右下で、3 人目からは残りの角
+        let (right, top) = (self.maze_width() - 2, self.maze_height() - 2);
+        let corners = [
+            IVec2::new(1, top),
+            IVec2::new(right, 1),
+            IVec2::new(right, top),
+            IVec2::new(1, 1),
+        ];
+        self.player_init_pos = corners[..self.player_num].to_vec();
+    }
+
+    // 設定に合わせて迷路を作る
+    fn generate_maze(&self, rng: &mut StdRng) -> Maze {
+        let mut maze =
+            self.algorithm
+                .generator()
+                .generate(self.maze_width(), self.maze_height(), rng);
+        maze.braid(self.braid, rng);
+        // 壊した壁も一緒に写るように、対称にするのは最後
+        if self.symmetric {
+            maze.symmetrize(rng);
         }
+        maze
+    }
+
+    // プレイヤーの初期位置のマス
+    fn start_cells(&self) -> Vec<Cell> {
+        self.player_init_pos.iter().map(|&pos| pos.into()).collect()
+    }
+
+    // 一緒に出すアイテムの数 (点対称なら対称な位置、釣り合わせるなら道のりが逆の二つ)
+    fn item_group(&self) -> usize {
+        if self.symmetric || self.placement == ItemPlacement::Balanced {
+            2
+        } else {
+            1
+        }
+    }
+
+    // ラウンドで出るアイテムの数 (一緒に出す数の倍数に切り捨てる)
+    fn total_item_num(&self) -> usize {
+        let group = self.item_group();
+        (self.item_num / group * group).max(group)
+    }
+
+    // 同時に置かれるアイテムの最大数 (0 なら制限なし、一緒に出す数の倍数に切り上げる)
+    fn field_item_num(&self) -> usize {
+        self.max_items_on_field.div_ceil(self.item_group()) * self.item_group()
+    }
+
+    // 今のラウンドの迷路のマス数 (外周の壁を含む)
+    fn maze_width(&self) -> i32 {
+        self.maze_size.x
+    }
+    fn maze_height(&self) -> i32 {
+        self.maze_size.y
+    }
+
+    // 設定の表示内容
+    fn setting_value(&self, label: &str) -> String {
+        match label {
+            "ItemNum: " => self.total_item_num().to_string(),
+            "OnField: " => match self.field_item_num() {
+                0 => "All".to_string(),
+                max => max.to_string(),
+            },
+            "Respawn: " => format!("{}s", self.respawn_delay),
+            "Placement: " => self.placement.name().to_string(),
+            "Width: " => self.block_num_x.to_string(),
+            "Height: " => self.block_num_y.to_string(),
+            "Algorithm: " => self.algorithm.name().to_string(),
+            "Braid: " => format!("{:.0}%", self.braid * 100.),
+            "Symmetric: " => if self.symmetric { "On" } else { "Off" }.to_string(),
+            "Solver: " => if self.show_solver { "On" } else { "Off" }.to_string(),
+            "Fog: " => self.fog.name().to_string(),
+            "Split: " => if self.split_screen { "On" } else { "Off" }.to_string(),
+            "Rounds: " => self.rounds.to_string(),
+            "Players: " => self.player_num.to_string(),
+            "Movement: " => self.movement.name().to_string(),
+            "Mode: " => self.mode.name().to_string(),
+            "TimeLimit: " => match self.time_limit {
+                0 => "Off".to_string(),
+                limit => format!("{limit}s"),
+            },
+            _ => String::new(),
+        }
+    }
+
+    // ワールド座標があるマス
+    fn world_to_grid(&self, pos: Vec2) -> IVec2 {
+        (pos / self.block_size + self.grid_center())
+            .round()
+            .as_ivec2()
+    }
+
+    // マスの座標をいい感じの位置にするための計算
+    fn grid_to_world(&self, pos: IVec2) -> Vec2 {
+        self.block_size * (pos.as_vec2() - self.grid_center())
+    }
+
+    // 迷路の中心 (ワールド座標の原点) のマス座標
+    fn grid_center(&self) -> Vec2 {
+        IVec2::new(self.maze_width() - 1, self.maze_height() - 1).as_vec2() / 2.
+    }
+}
 
-        let block_x = Self::WALL_MAX / (self.block_num + 2.0);
-        let block_y = Self::WALL_MAX / (self.block_num + 2.0);
-
-        self.block_size = Vec2::new(block_x, block_y);
-        self.player_size = Vec2::new(block_x / 2.0, block_y / 2.0);
-        self.player_speed = ((4000. / self.block_num) + (4000. / self.block_num)) / 2.;
-        self.player1_init_pos = Vec2::new(
-            ((self.block_num + 1.) / 2.) * -block_x,
-            ((self.block_num + 1.) / 2.) * block_y,
-        );
-        self.player2_init_pos = Vec2::new(
-            ((self.block_num + 1.) / 2.) * block_x,
-            ((self.block_num + 1.) / 2.) * -block_y,
-        );
-    }
-
-    fn wall_adjustment(&self, x: f32, y: f32) -> Vec2 {
-        let x = self.block_size.x * (x - 2.)
-            - self.block_size.x * (self.block_num / 2.)
-            - self.block_size.x / 2.;
-        let y = self.block_size.y * (y - 2.)
-            - self.block_size.y * (self.block_num / 2.)
-            - self.block_size.y / 2.;
-        Vec2::new(x, y)
-    }
-    fn block_adjustment(&self, x: f32, y: f32) -> Vec2 {
-        let x = self.block_size.x * x
-            - self.block_size.x * (self.block_num / 2.)
-            - self.block_size.x / 2.;
-        let y = self.block_size.y * y
-            - self.block_size.y * (self.block_num / 2.)
-            - self.block_size.y / 2.;
-        Vec2::new(x, y)
-    }
-}
-
-const SETTING_SECTION: [(&str, (ButtonAction, &str), (ButtonAction, &str)); 2] = [
+const SETTING_SECTION: [(&str, (ButtonAction, &str), (ButtonAction, &str)); 17] = [
+    (
+        "Players: ",
+        (ButtonAction::PlayersDown, "<"),
+        (ButtonAction::PlayersUp, ">"),
+    ),
+    (
+        "Movement: ",
+        (ButtonAction::MovementPrev, "<"),
+        (ButtonAction::MovementNext, ">"),
+    ),
+    (
+        "Mode: ",
+        (ButtonAction::ModePrev, "<"),
+        (ButtonAction::ModeNext, ">"),
+    ),
     (
         "ItemNum: ",
         (ButtonAction::ItemNumDown, "<"),
         (ButtonAction::ItemNumUp, ">"),
     ),
     (
-        "BlockNum: ",
-        (ButtonAction::BlockNumDown, "<"),
-        (ButtonAction::BlockNumUp, ">"),
+        "OnField: ",
+        (ButtonAction::OnFieldDown, "<"),
+        (ButtonAction::OnFieldUp, ">"),
+    ),
+    (
+        "Respawn: ",
+        (ButtonAction::RespawnDown, "<"),
+        (ButtonAction::RespawnUp, ">"),
+    ),
+    (
+        "Placement: ",
+        (ButtonAction::PlacementPrev, "<"),
+        (ButtonAction::PlacementNext, ">"),
+    ),
+    (
+        "Width: ",
+        (ButtonAction::WidthDown, "<"),
+        (ButtonAction::WidthUp, ">"),
+    ),
+    (
+        "Height: ",
+        (ButtonAction::HeightDown, "<"),
+        (ButtonAction::HeightUp, ">"),
+    ),
+    (
+        "Algorithm: ",
+        (ButtonAction::AlgorithmPrev, "<"),
+        (ButtonAction::AlgorithmNext, ">"),
+    ),
+    (
+        "Braid: ",
+        (ButtonAction::BraidDown, "<"),
+        (ButtonAction::BraidUp, ">"),
+    ),
+    (
+        "Symmetric: ",
+        (ButtonAction::SymmetricToggle, "<"),
+        (ButtonAction::SymmetricToggle, ">"),
+    ),
+    (
+        "Split: ",
+        (ButtonAction::SplitToggle, "<"),
+        (ButtonAction::SplitToggle, ">"),
+    ),
+    (
+        "Fog: ",
+        (ButtonAction::FogPrev, "<"),
+        (ButtonAction::FogNext, ">"),
+    ),
+    (
+        "Solver: ",
+        (ButtonAction::SolverToggle, "<"),
+        (ButtonAction::SolverToggle, ">"),
+    ),
+    (
+        "Rounds: ",
+        (ButtonAction::RoundsDown, "<"),
+        (ButtonAction::RoundsUp, ">"),
+    ),
+    (
+        "TimeLimit: ",
+        (ButtonAction::TimeLimitDown, "<"),
+        (ButtonAction::TimeLimitUp, ">"),
     ),
 ];
 
-fn setup(mut commands: Commands, game: Res<Game>) {
-    commands.spawn(Camera2dBundle::default());
+fn setup(mut commands: Commands, game: Res<Game>, seed: Res<MazeSeed>) {
+    commands.spawn((Camera2dBundle::default(), camera::MainCamera));
 
     let text_style = TextStyle {
         font_size: SCOREBOARD_FONT_SIZE,
@@ -258,40 +746,79 @@ fn setup(mut commands: Commands, game: Res<Game>) {
         ..default()
     };
 
-    commands.spawn((
-        TextBundle::from_sections([
-            TextSection::new("p1: ", text_style.clone()),
-            TextSection::new("0", text_style.clone()),
-        ])
-        .with_style(Style {
+    // 奇数番目は左上、偶数番目は右上に縦に並べる
+    for (id, color) in PLAYER_COLORS.into_iter().enumerate() {
+        let top = Val::Px(8.0 + SCOREBOARD_FONT_SIZE * (id / 2) as f32);
+        let mut style = Style {
             position_type: PositionType::Absolute,
-            justify_self: JustifySelf::End,
-            top: SCOREBOARD_TEXT_PADDING,
-            left: SCOREBOARD_TEXT_PADDING,
+            top,
             ..default()
-        }),
-        ScoreboardSection::P1,
-    ));
+        };
+        if id % 2 == 0 {
+            style.left = SCOREBOARD_TEXT_PADDING;
+        } else {
+            style.right = SCOREBOARD_TEXT_PADDING;
+        }
+
+        commands.spawn((
+            TextBundle::from_sections([
+                TextSection::new(
+                    format!("p{}: ", id + 1),
+                    TextStyle {
+                        color,
+                        ..text_style.clone()
+                    },
+                ),
+                TextSection::new("0", text_style.clone()),
+            ])
+            .with_style(style),
+            ScoreboardSection(id),
+        ));
+    }
     commands.spawn((
         TextBundle::from_sections([
-            TextSection::new("p2: ", text_style.clone()),
-            TextSection::new("0", text_style),
+            TextSection::new(
+                "seed: ",
+                TextStyle {
+                    font_size: 25.,
+                    color: TEXT_COLOR,
+                    ..default()
+                },
+            ),
+            TextSection::new(
+                seed.0.to_string(),
+                TextStyle {
+                    font_size: 25.,
+                    color: TEXT_COLOR,
+                    ..default()
+                },
+            ),
         ])
         .with_style(Style {
             position_type: PositionType::Absolute,
-            top: SCOREBOARD_TEXT_PADDING,
-            right: SCOREBOARD_TEXT_PADDING,
+            bottom: SCOREBOARD_TEXT_PADDING,
+            left: SCOREBOARD_TEXT_PADDING,
             ..default()
         }),
-        ScoreboardSection::P2,
+        SeedText,
     ));
 
+    // 設定が増えてきたので一行に "< 名前: 値 >" と並べる
     let button_style = Style {
-        width: Val::Px(50.),
-        height: Val::Px(40.),
+        width: Val::Px(36.),
+        height: Val::Px(30.),
         align_items: AlignItems::Center,
         justify_content: JustifyContent::Center,
-        margin: UiRect::horizontal(Val::Px(8.)),
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 28.,
+        color: Color::BLACK,
+        ..default()
+    };
+    let setting_text_style = TextStyle {
+        font_size: 24.,
+        color: Color::WHITE,
         ..default()
     };
     let text_style = TextStyle {
@@ -316,32 +843,11 @@ fn setup(mut commands: Commands, game: Res<Game>) {
         })
         .with_children(|parent| {
             for section in SETTING_SECTION {
-                parent.spawn(TextBundle::from_sections([
-                    TextSection::new(
-                        section.0,
-                        TextStyle {
-                            font_size: 35.,
-                            color: Color::WHITE,
-                            ..default()
-                        },
-                    ),
-                    TextSection::new(
-                        if section.0 == "ItemNum: " {
-                            game.item_num.to_string()
-                        } else {
-                            game.block_num.to_string()
-                        },
-                        TextStyle {
-                            font_size: 35.,
-                            color: Color::WHITE,
-                            ..default()
-                        },
-                    ),
-                ]));
                 parent
                     .spawn(NodeBundle {
                         style: Style {
-                            padding: UiRect::vertical(Val::Px(6.)),
+                            align_items: AlignItems::Center,
+                            padding: UiRect::vertical(Val::Px(3.)),
                             ..default()
                         },
                         ..default()
@@ -358,9 +864,27 @@ fn setup(mut commands: Commands, game: Res<Game>) {
                             .with_children(|parent| {
                                 parent.spawn(TextBundle::from_section(
                                     section.1 .1,
-                                    text_style.clone(),
+                                    button_text_style.clone(),
                                 ));
                             });
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    width: Val::Px(220.),
+                                    justify_content: JustifyContent::Center,
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_sections([
+                                    TextSection::new(section.0, setting_text_style.clone()),
+                                    TextSection::new(
+                                        game.setting_value(section.0),
+                                        setting_text_style.clone(),
+                                    ),
+                                ]));
+                            });
                         parent
                             .spawn((
                                 ButtonBundle {
@@ -372,7 +896,7 @@ fn setup(mut commands: Commands, game: Res<Game>) {
                             .with_children(|parent| {
                                 parent.spawn(TextBundle::from_section(
                                     section.2 .1,
-                                    text_style.clone(),
+                                    button_text_style.clone(),
                                 ));
                             });
                     });
@@ -392,308 +916,288 @@ fn setup(mut commands: Commands, game: Res<Game>) {
                     ButtonAction::Shuffle,
                 ))
                 .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section("Shuffle", text_style));
+                    parent.spawn(TextBundle::from_section("Shuffle", text_style.clone()));
+                });
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(230.),
+                            height: Val::Px(60.),
+                            margin: UiRect::top(Val::Px(6.)),
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::Center,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    ButtonAction::Controls,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Controls", text_style));
                 });
         });
 }
 
-// アイテムを作る
-fn create_item(mut commands: Commands, item_position: Res<ItemPosition>, game: Res<Game>) {
-    for i in 0..game.item_num {
-        commands.spawn(ItemBundle::new(
-            Item::Item,
-            item_position.0[i],
-            game.player_size,
-        ));
-    }
-}
-
 fn labyrinth_setup(
     mut commands: Commands,
-    entity_query: Query<Entity, Or<(With<Player1>, With<Player2>, With<Block>, With<Item>)>>,
+    key_config: Res<KeyConfig>,
+    entity_query: Query<Entity, Or<(With<Player>, With<Block>, With<Item>, With<Goal>)>>,
     mut game: ResMut<Game>,
     mut resource_item_pos: ResMut<ItemPosition>,
+    mut rules: ResMut<RoundRules>,
+    mut labyrinth: ResMut<Labyrinth>,
+    seed: Res<MazeSeed>,
+    mut game_rng: ResMut<GameRng>,
     mut state: ResMut<NextState<Status>>,
 ) {
     game.init();
+    *rules = RoundRules {
+        mode: game.mode,
+        player_num: game.player_init_pos.len(),
+        fog: game.fog,
+        split_screen: game.split_screen,
+        item_group: game.item_group(),
+        item_num: game.total_item_num(),
+        max_items_on_field: game.field_item_num(),
+    };
 
     for entity in &entity_query {
         commands.entity(entity).despawn();
     }
 
-    commands.spawn((
-        SpriteBundle {
-            transform: Transform {
-                translation: game.player1_init_pos.extend(0.0),
-                scale: game.player_size.extend(0.0),
-                ..default()
-            },
-            sprite: Sprite {
-                color: Color::RED,
-                ..default()
-            },
-            ..default()
-        },
-        Player1,
-    ));
-    commands.spawn((
-        SpriteBundle {
-            transform: Transform {
-                translation: game.player2_init_pos.extend(0.0),
-                scale: game.player_size.extend(0.0),
-                ..default()
-            },
-            sprite: Sprite {
-                color: Color::GREEN,
+    for (id, &pos) in game.player_init_pos.iter().enumerate() {
+        commands.spawn((
+            SpriteBundle {
+                transform: Transform {
+                    translation: game.grid_to_world(pos).extend(0.0),
+                    scale: game.player_size.extend(0.0),
+                    ..default()
+                },
+                sprite: Sprite {
+                    color: PLAYER_COLORS[id],
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        },
-        Player2,
-    ));
-
-    let block_num = game.block_num as i32;
-
-    // 壁
-    for y in 1..=block_num + 4 {
-        for x in 1..=block_num + 4 {
-            if y == 1 || x == 1 || y == block_num + 4 || x == block_num + 4 {
-                // 座標をいい感じの位置にするための計算
-                let pos = game.wall_adjustment(x as f32, y as f32);
+            GridPos(pos),
+            Effects::default(),
+            GridMove::default(),
+            key_config.players[id].clone(),
+            Player { id },
+        ));
+    }
 
-                commands.spawn(BlockBundle::new(pos, game.block_size));
+    // 同じシード値なら同じ迷路になる
+    game_rng.0 = seed.rng();
+    let rng = &mut game_rng.0;
+    let mut maze = game.generate_maze(rng);
+    let starts = game.start_cells();
+
+    if rules.mode == GameMode::ExitRace {
+        // どのプレイヤーからも同じ距離のマスをゴールにする
+        // 3 人以上だとそんなマスが無いことが多いので、見つかるまで迷路を作り直す
+        let mut goal = maze.fair_goal(&starts, rng);
+        for _ in 1..FAIR_GOAL_ATTEMPTS {
+            if goal.is_none_or(|(_, gap)| gap == 0) {
+                break;
             }
+            maze = game.generate_maze(rng);
+            goal = maze.fair_goal(&starts, rng);
         }
-    }
-
-    let mut item_position: Vec<Vec2> = Vec::new();
-    let mut block_position: Vec<Vec2> = Vec::new();
-
-    for y in (1..=block_num).step_by(2) {
-        for x in (1..=block_num).step_by(2) {
-            // 座標をいい感じの位置にするための計算
-            let pos = game.block_adjustment(x as f32, y as f32);
-
-            // 棒
-            commands.spawn(BlockBundle::new(pos, game.block_size));
-
-            // 倒した棒の位置
-            let block_pos = knock_down_the(
-                &mut item_position,
-                pos.x,
-                pos.y,
-                game.block_size.x,
-                game.block_size.y,
-            );
 
-            block_position.push(block_pos);
+        if let Some((goal, gap)) = goal {
+            if gap > 0 {
+                warn!("全員から同じ距離のゴールが作れませんでした (差 {gap} マス)");
+            }
 
-            commands.spawn(BlockBundle::new(block_pos, game.block_size));
+            commands.spawn((
+                SpriteBundle {
+                    transform: Transform {
+                        // プレイヤーより後ろに描画する
+                        translation: Vec3::new(0., 0., -1.),
+                        scale: game.block_size.extend(0.0),
+                        ..default()
+                    },
+                    sprite: Sprite {
+                        color: Color::GOLD,
+                        ..default()
+                    },
+                    ..default()
+                },
+                GridPos(goal.into()),
+                Goal,
+            ));
         }
     }
+    debug_assert!(
+        maze.is_connected(&starts),
+        "迷路に辿り着けない場所があります\n{maze}"
+    );
 
-    // ブロックがある位置にアイテムが作られないように
-    for block in block_position {
-        overlapping_remove(&mut item_position, block);
+    // 壁
+    for cell in maze.walls() {
+        commands.spawn(BlockBundle::new(cell.into(), game.block_size));
     }
 
-    // アイテムをシャッフル
-    item_position.shuffle(&mut thread_rng());
+    // アイテムは通路にだけ作る
+    let item_position: Vec<IVec2> = match game.placement {
+        // 対称な位置に置けば、どちらのプレイヤーからも同じ道のりになる
+        _ if game.symmetric => maze
+            .symmetric_passages(rng)
+            .into_iter()
+            .map(IVec2::from)
+            .collect(),
+        ItemPlacement::Random => {
+            let mut item_position: Vec<IVec2> = maze.passages().map(IVec2::from).collect();
+            // アイテムをシャッフル
+            item_position.shuffle(rng);
+            item_position
+        }
+        ItemPlacement::Balanced => maze
+            .balanced_passages(&starts, rng)
+            .into_iter()
+            .map(IVec2::from)
+            .collect(),
+    };
     // アイテムの位置を更新する
     resource_item_pos.0 = item_position;
+    labyrinth.0 = maze;
 
     // ステータスの変更
     state.set(Status::Disabled);
 }
 
-// valueと重複する値を消去する
-#[inline]
-fn overlapping_remove(vec: &mut Vec<Vec2>, value: Vec2) {
-    // 重複する要素があるインデックスを取得し、削除
-    // ※ as_ivec2()メソッドを使用してf32をi32にしないと判定漏れが発生する
-    if let Some(index) = vec.iter().position(|&i| i.as_ivec2() == value.as_ivec2()) {
-        vec.remove(index);
-    }
-}
-
-//倒した棒の座標
-#[inline]
-fn knock_down_the(vec: &mut Vec<Vec2>, x: f32, y: f32, block_x: f32, block_y: f32) -> Vec2 {
-    // 十字の方向
-    let mut cross_array: Vec<Vec2> = vec![
-        Vec2::new(x, y + block_y),
-        Vec2::new(x + block_x, y),
-        Vec2::new(x, y - block_y),
-        Vec2::new(x - block_x, y),
-    ];
-    // 斜めの座標
-    let slantings_array: [Vec2; 4] = [
-        Vec2::new(x + block_x, y + block_y),
-        Vec2::new(x + block_x, y - block_y),
-        Vec2::new(x - block_x, y + block_y),
-        Vec2::new(x - block_x, y - block_y),
-    ];
-
-    cross_array.shuffle(&mut thread_rng());
-
-    let block = cross_array.pop().expect("pop エラー");
-
-    for value in cross_array {
-        vec.push(value);
-    }
-    for value in slantings_array {
-        vec.push(value);
-    }
-
-    block
-}
-
 // プレイヤーを動かす
-fn move_player1(
-    mut player_query: Query<&mut Transform, With<Player1>>,
-    key: Res<Input<KeyCode>>,
+fn move_player(
+    mut player_query: Query<(&mut Transform, &Effects, &Controls, &Player, Option<&Bot>)>,
+    input: PlayerInput,
     game: Res<Game>,
     time: Res<Time>,
 ) {
-    let mut transform = player_query.single_mut();
+    for (mut transform, effects, controls, player, bot) in &mut player_query {
+        if effects.is_frozen() {
+            continue;
+        }
+        let speed = game.player_speed * effects.speed_rate();
 
-    // 方向
-    let mut direction_x = 0.0;
-    let mut direction_y = 0.0;
+        // 方向
+        let direction = match bot {
+            Some(bot) => bot.direction,
+            None => input.direction(controls, player),
+        };
 
-    if key.pressed(KeyCode::W) {
-        direction_y += 1.0
-    }
-    if key.pressed(KeyCode::S) {
-        direction_y -= 1.0;
-    }
-    if key.pressed(KeyCode::D) {
-        direction_x += 1.0
-    }
-    if key.pressed(KeyCode::A) {
-        direction_x -= 1.0;
+        // 座標を更新
+        transform.translation += (direction * speed * time.delta_seconds()).extend(0.0);
     }
-
-    // 座標を更新
-    transform.translation.x += direction_x * game.player_speed * time.delta_seconds();
-    transform.translation.y += direction_y * game.player_speed * time.delta_seconds();
 }
 
-fn move_player2(
-    mut player_query: Query<&mut Transform, With<Player2>>,
-    key: Res<Input<KeyCode>>,
+// 壁の判定
+// プレイヤーの周りのマスだけを調べ、めり込みが少ない方向に押し戻す
+// 押し戻すのは片方の軸だけなので、壁に沿って滑るように動ける
+fn wall_collision(
+    mut player_query: Query<(&mut Transform, &Effects), With<Player>>,
+    labyrinth: Res<Labyrinth>,
     game: Res<Game>,
-    time: Res<Time>,
 ) {
-    let mut transform = player_query.single_mut();
-
-    // 方向
-    let mut direction_x = 0.0;
-    let mut direction_y = 0.0;
+    for (mut player_transform, effects) in &mut player_query {
+        let center = game.world_to_grid(player_transform.translation.truncate());
+
+        // すり抜け中や通路に出した時は、壁との押し戻しはしない
+        let pos = player_transform.translation.truncate();
+        let confined = movement::confine_to_maze(pos, effects, &labyrinth, &game);
+        if effects.can_pass_wall() || confined != pos {
+            player_transform.translation = confined.extend(player_transform.translation.z);
+            continue;
+        }
 
-    if key.pressed(KeyCode::Up) {
-        direction_y += 1.0
-    }
-    if key.pressed(KeyCode::Down) {
-        direction_y -= 1.0;
-    }
-    if key.pressed(KeyCode::Right) {
-        direction_x += 1.0
-    }
-    if key.pressed(KeyCode::Left) {
-        direction_x -= 1.0;
+        // 重なりが大きい壁から先に処理する
+        let mut walls: Vec<(Vec2, f32)> = (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| center + IVec2::new(dx, dy)))
+            .filter(|&pos| labyrinth.0.is_wall(pos.into()))
+            .map(|pos| game.grid_to_world(pos))
+            .filter_map(|pos| {
+                let overlap = overlap(player_transform.translation.truncate(), pos, &game)?;
+                Some((pos, overlap.x * overlap.y))
+            })
+            .collect();
+        walls.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        for (pos, _) in walls {
+            let player_pos = player_transform.translation.truncate();
+            // 前の壁で押し戻されて、もう重なっていないことがある
+            let Some(overlap) = overlap(player_pos, pos, &game) else {
+                continue;
+            };
+
+            if overlap.x < overlap.y {
+                player_transform.translation.x += overlap.x * (player_pos.x - pos.x).signum();
+            } else {
+                player_transform.translation.y += overlap.y * (player_pos.y - pos.y).signum();
+            }
+        }
     }
+}
 
-    // 座標を更新
-    transform.translation.x += direction_x * game.player_speed * time.delta_seconds();
-    transform.translation.y += direction_y * game.player_speed * time.delta_seconds();
+// プレイヤーと壁がどれだけ重なっているか
+fn overlap(player_pos: Vec2, wall_pos: Vec2, game: &Game) -> Option<Vec2> {
+    let overlap = (game.player_size + game.block_size) / 2. - (player_pos - wall_pos).abs();
+    (overlap.x > 0. && overlap.y > 0.).then_some(overlap)
 }
 
-// TODO: 別の方法で実装したい
-// 今のままだと引っ掛かりがある
-// 壁の判定
-fn wall_collision(
-    mut player_query: Query<&mut Transform, (Or<(With<Player1>, With<Player2>)>, Without<Block>)>,
-    block_query: Query<&Transform, With<Block>>,
+// プレイヤーがいるマスを更新
+fn player_grid_pos_update(
+    mut player_query: Query<(&Transform, &mut GridPos), With<Player>>,
     game: Res<Game>,
-    time: Res<Time>,
 ) {
-    for mut player_transform in &mut player_query {
-        for transform in &block_query {
-            let collision = collide(
-                transform.translation,
-                transform.scale.xy(),
-                player_transform.translation,
-                player_transform.scale.xy(),
-            );
-            // 衝突したなら
-            if let Some(collision) = collision {
-                let mut direction_x = 0.0;
-                let mut direction_y = 0.0;
-
-                match collision {
-                    Collision::Top => direction_y -= 1.0,
-                    Collision::Bottom => direction_y += 1.0,
-                    Collision::Right => direction_x -= 1.0,
-                    Collision::Left => direction_x += 1.0,
-                    _ => (),
-                }
-
-                player_transform.translation.x +=
-                    direction_x * game.player_speed * time.delta_seconds();
-                player_transform.translation.y +=
-                    direction_y * game.player_speed * time.delta_seconds();
-            }
+    for (transform, mut grid_pos) in &mut player_query {
+        let pos = game.world_to_grid(transform.translation.truncate());
+        if grid_pos.0 != pos {
+            grid_pos.0 = pos;
         }
     }
 }
 
-// アイテムの判定
-fn item_collision<T>(
-    mut commands: Commands,
-    player_query: Query<&Transform, With<T>>,
-    item_query: Query<(Entity, &Transform, &Item), With<Item>>,
-    mut score_board: ResMut<ScoreBoard>,
-    mut event: EventWriter<ScoreEvent>,
-) where
-    T: Component + PlayerMethod,
-{
-    let player_transform = player_query.single();
-
-    for (entity, transform, _) in &item_query {
-        let collision = collide(
-            player_transform.translation,
-            player_transform.scale.truncate(),
-            transform.translation,
-            transform.scale.truncate(),
-        );
-        if collision.is_some() {
-            match T::section() {
-                ScoreboardSection::P1 => score_board.player1 += 1,
-                ScoreboardSection::P2 => score_board.player2 += 1,
-            }
-
-            event.send_default();
-
-            commands.entity(entity).despawn();
-        }
+// マスの座標から描画する位置を計算する
+// プレイヤーは自由に動くので除く
+fn grid_transform_update(
+    mut query: Query<(&GridPos, &mut Transform), (Changed<GridPos>, Without<Player>)>,
+    game: Res<Game>,
+) {
+    for (pos, mut transform) in &mut query {
+        transform.translation = game.grid_to_world(pos.0).extend(transform.translation.z);
     }
 }
 
 // スコアボードの更新
+// 遊んでいないプレイヤーのスコアは隠す
 fn score_board_update(
     score_board: Res<ScoreBoard>,
-    mut query: Query<(&mut Text, &ScoreboardSection)>,
+    rules: Res<RoundRules>,
+    mut query: Query<(&mut Text, &mut Visibility, &ScoreboardSection)>,
     mut event: EventReader<ScoreEvent>,
 ) {
-    if !event.is_empty() {
+    if !event.is_empty() || rules.is_changed() {
         event.clear();
 
-        for (mut text, section) in &mut query {
-            text.sections[1].value = match section {
-                ScoreboardSection::P1 => score_board.player1.to_string(),
-                ScoreboardSection::P2 => score_board.player2.to_string(),
-            }
+        for (mut text, mut visibility, section) in &mut query {
+            text.sections[1].value = score_board.get(section.0).to_string();
+            *visibility = if section.0 < rules.player_num {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+}
+
+// シード値の表示を更新
+fn seed_text_update(seed: Res<MazeSeed>, mut query: Query<&mut Text, With<SeedText>>) {
+    if seed.is_changed() {
+        info!("seed: {}", seed.0);
+
+        for mut text in &mut query {
+            text.sections[1].value = seed.0.to_string();
         }
     }
 }
@@ -705,23 +1209,86 @@ fn button_system(
     >,
     mut text_query: Query<&mut Text>,
     mut game: ResMut<Game>,
+    mut seed: ResMut<MazeSeed>,
+    mut match_score: ResMut<round::MatchScore>,
+    mut controls_screen: ResMut<controls::ControlsScreen>,
     mut status: ResMut<NextState<Status>>,
 ) {
     for (interaction, mut background, action) in &mut interaction_query {
         match *interaction {
             Interaction::Pressed => {
                 match *action {
-                    ButtonAction::Shuffle => status.set(Status::Shuffle),
+                    // 試合の成績はそのままで、このラウンドの迷路を作り直す
+                    ButtonAction::NextRound | ButtonAction::Shuffle => {
+                        seed.reroll();
+                        status.set(Status::Shuffle);
+                    }
+                    // 試合を最初からやり直す
+                    ButtonAction::NewMatch => {
+                        *match_score = round::MatchScore::default();
+                        seed.reroll();
+                        status.set(Status::Shuffle);
+                    }
                     ButtonAction::ItemNumUp => game.item_num += 1,
-                    ButtonAction::ItemNumDown => game.item_num -= 1,
-                    ButtonAction::BlockNumUp => game.block_num += 2.,
-                    ButtonAction::BlockNumDown => game.block_num -= 2.,
+                    // アイテムが無いとすぐにラウンドが終わってしまう
+                    ButtonAction::ItemNumDown => game.item_num = (game.item_num - 1).max(1),
+                    ButtonAction::WidthUp => game.block_num_x += 2,
+                    ButtonAction::WidthDown => game.block_num_x -= 2,
+                    ButtonAction::HeightUp => game.block_num_y += 2,
+                    ButtonAction::HeightDown => game.block_num_y -= 2,
+                    ButtonAction::AlgorithmNext => game.algorithm = game.algorithm.next(),
+                    ButtonAction::AlgorithmPrev => game.algorithm = game.algorithm.prev(),
+                    ButtonAction::BraidUp => game.braid = (game.braid + 0.1).min(1.),
+                    ButtonAction::BraidDown => game.braid = (game.braid - 0.1).max(0.),
+                    ButtonAction::SymmetricToggle => game.symmetric = !game.symmetric,
+                    ButtonAction::SolverToggle => game.show_solver = !game.show_solver,
+                    ButtonAction::SplitToggle => game.split_screen = !game.split_screen,
+                    ButtonAction::FogNext => game.fog = game.fog.next(),
+                    ButtonAction::FogPrev => game.fog = game.fog.prev(),
+                    ButtonAction::PlayersUp => {
+                        game.player_num = (game.player_num + 1).min(MAX_PLAYERS)
+                    }
+                    ButtonAction::PlayersDown => {
+                        game.player_num = (game.player_num - 1).max(MIN_PLAYERS)
+                    }
+                    ButtonAction::MovementNext | ButtonAction::MovementPrev => {
+                        game.movement = game.movement.toggle()
+                    }
+                    ButtonAction::RoundsUp => game.rounds += 1,
+                    ButtonAction::RoundsDown => game.rounds = (game.rounds - 1).max(1),
+                    ButtonAction::ModeNext | ButtonAction::ModePrev => {
+                        game.mode = game.mode.toggle()
+                    }
+                    ButtonAction::OnFieldUp => game.max_items_on_field += 1,
+                    ButtonAction::OnFieldDown => {
+                        game.max_items_on_field = game.max_items_on_field.saturating_sub(1)
+                    }
+                    ButtonAction::PlacementNext | ButtonAction::PlacementPrev => {
+                        game.placement = game.placement.toggle()
+                    }
+                    ButtonAction::RespawnUp => game.respawn_delay += RESPAWN_DELAY_STEP,
+                    ButtonAction::RespawnDown => {
+                        game.respawn_delay = (game.respawn_delay - RESPAWN_DELAY_STEP).max(0.)
+                    }
+                    ButtonAction::Controls => {
+                        controls_screen.open = !controls_screen.open;
+                        controls_screen.waiting = None;
+                    }
+                    ButtonAction::Rebind(player, action) => {
+                        controls_screen.waiting = Some((player, action))
+                    }
+                    ButtonAction::CpuNext(player) => {
+                        game.cpu[player] = ai::Difficulty::cycle(game.cpu[player])
+                    }
+                    ButtonAction::TimeLimitUp => game.time_limit += TIME_LIMIT_STEP,
+                    ButtonAction::TimeLimitDown => {
+                        game.time_limit = game.time_limit.saturating_sub(TIME_LIMIT_STEP)
+                    }
                 };
                 for mut text in &mut text_query {
-                    match text.sections[0].value.as_str() {
-                        "ItemNum: " => text.sections[1].value = game.item_num.to_string(),
-                        "BlockNum: " => text.sections[1].value = game.block_num.to_string(),
-                        _ => (),
+                    let value = game.setting_value(&text.sections[0].value);
+                    if !value.is_empty() {
+                        text.sections[1].value = value;
                     }
                 }
             }
@@ -730,20 +1297,3 @@ fn button_system(
         }
     }
 }
-
-trait PlayerMethod {
-    // どのスコアを変更するればいいかを返す
-    fn section() -> ScoreboardSection;
-}
-
-impl PlayerMethod for Player1 {
-    fn section() -> ScoreboardSection {
-        ScoreboardSection::P1
-    }
-}
-
-impl PlayerMethod for Player2 {
-    fn section() -> ScoreboardSection {
-        ScoreboardSection::P2
-    }
-}
@@ -0,0 +1,420 @@
+mod generator;
+mod path;
+
+use std::{collections::VecDeque, fmt};
+
+use rand::{seq::SliceRandom, Rng};
+
+pub use generator::Algorithm;
+pub use path::DistanceMap;
+
+// マスの状態
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Tile {
+    Wall,
+    #[default]
+    Passage,
+}
+
+// 迷路上のマスの座標 (左下が原点)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cell {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Cell {
+    pub const fn new(x: i32, y: i32) -> Cell {
+        Cell { x, y }
+    }
+
+    // 上下左右のマス (上、右、下、左の順)
+    pub const fn cross(self) -> [Cell; 4] {
+        [
+            Cell::new(self.x, self.y + 1),
+            Cell::new(self.x + 1, self.y),
+            Cell::new(self.x, self.y - 1),
+            Cell::new(self.x - 1, self.y),
+        ]
+    }
+}
+
+// 描画に依存しない迷路のデータ
+// 外周は必ず壁になる
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Maze {
+    width: i32,
+    height: i32,
+    tiles: Vec<Tile>,
+}
+
+impl Maze {
+    // 外周だけが壁の迷路を作る
+    pub fn new(width: i32, height: i32) -> Maze {
+        let width = width.max(3);
+        let height = height.max(3);
+
+        let mut maze = Maze {
+            width,
+            height,
+            tiles: vec![Tile::Passage; (width * height) as usize],
+        };
+        for y in 0..height {
+            for x in 0..width {
+                if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                    maze.set(Cell::new(x, y), Tile::Wall);
+                }
+            }
+        }
+        maze
+    }
+
+    // 全てが壁の迷路を作る
+    pub fn filled(width: i32, height: i32) -> Maze {
+        let mut maze = Maze::new(width, height);
+        maze.tiles.fill(Tile::Wall);
+        maze
+    }
+
+    // 棒倒し法で迷路を作る
+    // 一番上の行の棒は四方向、それ以外の行は上以外の三方向に倒す
+    // 既に壁があるところには倒さないので、閉じた領域はできない
+    pub fn stick_knock_down<R: Rng + ?Sized>(width: i32, height: i32, rng: &mut R) -> Maze {
+        let mut maze = Maze::new(width, height);
+        let first_row = (maze.height - 3) / 2 * 2;
+
+        for y in (2..maze.height - 2).step_by(2).rev() {
+            for x in (2..maze.width - 2).step_by(2) {
+                let pole = Cell::new(x, y);
+                // 棒
+                maze.set(pole, Tile::Wall);
+
+                // 倒せる方向
+                let directions: Vec<Cell> = pole
+                    .cross()
+                    .into_iter()
+                    .enumerate()
+                    .filter(|&(i, _)| y == first_row || i != 0)
+                    .map(|(_, cell)| cell)
+                    .filter(|&cell| !maze.is_wall(cell))
+                    .collect();
+
+                let block = *directions.choose(rng).expect("choose エラー");
+                maze.set(block, Tile::Wall);
+            }
+        }
+
+        maze
+    }
+
+    // 行き止まりの壁を ratio の割合で壊してループを作る
+    // 壊すのは向こう側が通路になっている壁だけで、できれば行き止まり同士をつなぐ
+    pub fn braid<R: Rng + ?Sized>(&mut self, ratio: f32, rng: &mut R) {
+        let mut dead_ends: Vec<Cell> = self
+            .passages()
+            .filter(|&cell| self.is_dead_end(cell))
+            .collect();
+        dead_ends.shuffle(rng);
+
+        let count = (dead_ends.len() as f32 * ratio.clamp(0., 1.)).round() as usize;
+        for cell in dead_ends.into_iter().take(count) {
+            // 前に壊した壁で行き止まりでなくなっていることがある
+            if !self.is_dead_end(cell) {
+                continue;
+            }
+
+            let candidates: Vec<(Cell, Cell)> = cell
+                .cross()
+                .into_iter()
+                .map(|wall| (wall, Cell::new(wall.x * 2 - cell.x, wall.y * 2 - cell.y)))
+                .filter(|&(wall, beyond)| {
+                    self.is_wall(wall) && !self.is_border(wall) && !self.is_wall(beyond)
+                })
+                .collect();
+            let preferred: Vec<(Cell, Cell)> = candidates
+                .iter()
+                .copied()
+                .filter(|&(_, beyond)| self.is_dead_end(beyond))
+                .collect();
+
+            let choice = if preferred.is_empty() {
+                candidates.choose(rng)
+            } else {
+                preferred.choose(rng)
+            };
+            if let Some(&(wall, _)) = choice {
+                self.set(wall, Tile::Passage);
+            }
+        }
+    }
+
+    // 180度回転した位置のマス
+    pub const fn rotated(&self, cell: Cell) -> Cell {
+        Cell::new(self.width - 1 - cell.x, self.height - 1 - cell.y)
+    }
+
+    // 下半分を180度回転して上半分に写し、点対称の迷路にする
+    // 写した後に分かれてしまった通路は、壁とその対称の壁を一緒に壊してつなぐ
+    pub fn symmetrize<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        for cell in self.cells().collect::<Vec<_>>() {
+            let rotated = self.rotated(cell);
+            if (cell.y, cell.x) < (rotated.y, rotated.x) {
+                let tile = self.tile(cell);
+                self.set(rotated, tile);
+            }
+        }
+
+        loop {
+            let components = self.components();
+
+            // 両側が別々の通路になっている壁
+            let bridges: Vec<Cell> = self
+                .walls()
+                .filter(|&wall| !self.is_border(wall))
+                .filter(|&wall| {
+                    let [up, right, down, left] = wall.cross();
+                    [(up, down), (right, left)].into_iter().any(|(a, b)| {
+                        let (a, b) = (components[self.index(a)], components[self.index(b)]);
+                        a.is_some() && b.is_some() && a != b
+                    })
+                })
+                .collect();
+
+            let Some(&wall) = bridges.choose(rng) else {
+                break;
+            };
+            self.set(wall, Tile::Passage);
+            let rotated = self.rotated(wall);
+            self.set(rotated, Tile::Passage);
+        }
+    }
+
+    // 対称な位置のマスが続けて並ぶように通路を並べる
+    // 中心のマスは全員から同じ距離で取り合いになるので使わない
+    pub fn symmetric_passages<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<Cell> {
+        let mut half: Vec<Cell> = self
+            .passages()
+            .filter(|&cell| {
+                let rotated = self.rotated(cell);
+                (cell.y, cell.x) < (rotated.y, rotated.x)
+            })
+            .collect();
+        half.shuffle(rng);
+
+        let mut cells = Vec::with_capacity(half.len() * 2);
+        for cell in half {
+            cells.push(cell);
+            cells.push(self.rotated(cell));
+        }
+        cells
+    }
+
+    // つながっている通路ごとの番号
+    fn components(&self) -> Vec<Option<usize>> {
+        let mut components = vec![None; self.tiles.len()];
+        let mut count = 0;
+
+        for cell in self.passages() {
+            if components[self.index(cell)].is_some() {
+                continue;
+            }
+            let reached = self.reachable_from(cell);
+            for (component, reached) in components.iter_mut().zip(reached) {
+                if reached {
+                    *component = Some(count);
+                }
+            }
+            count += 1;
+        }
+
+        components
+    }
+
+    // 三方向が壁の通路
+    pub fn is_dead_end(&self, cell: Cell) -> bool {
+        !self.is_wall(cell)
+            && cell
+                .cross()
+                .into_iter()
+                .filter(|&next| self.is_wall(next))
+                .count()
+                == 3
+    }
+
+    // 外周の壁
+    pub const fn is_border(&self, cell: Cell) -> bool {
+        cell.x == 0 || cell.y == 0 || cell.x == self.width - 1 || cell.y == self.height - 1
+    }
+
+    pub const fn contains(&self, cell: Cell) -> bool {
+        cell.x >= 0 && cell.y >= 0 && cell.x < self.width && cell.y < self.height
+    }
+
+    // 範囲外は壁として扱う
+    pub fn tile(&self, cell: Cell) -> Tile {
+        if self.contains(cell) {
+            self.tiles[self.index(cell)]
+        } else {
+            Tile::Wall
+        }
+    }
+
+    pub fn is_wall(&self, cell: Cell) -> bool {
+        self.tile(cell) == Tile::Wall
+    }
+
+    pub fn set(&mut self, cell: Cell, tile: Tile) {
+        if self.contains(cell) {
+            let index = self.index(cell);
+            self.tiles[index] = tile;
+        }
+    }
+
+    // 全てのマス
+    pub fn cells(&self) -> impl Iterator<Item = Cell> + '_ {
+        (0..self.height).flat_map(move |y| (0..self.width).map(move |x| Cell::new(x, y)))
+    }
+
+    pub fn walls(&self) -> impl Iterator<Item = Cell> + '_ {
+        self.cells().filter(|&cell| self.is_wall(cell))
+    }
+
+    pub fn passages(&self) -> impl Iterator<Item = Cell> + '_ {
+        self.cells().filter(|&cell| !self.is_wall(cell))
+    }
+
+    // 隣り合う部屋 (二マス離れたマス) とその間の壁を通路にする
+    fn carve(&mut self, from: Cell, to: Cell) {
+        let between = Cell::new((from.x + to.x) / 2, (from.y + to.y) / 2);
+        for cell in [from, between, to] {
+            self.set(cell, Tile::Passage);
+        }
+    }
+
+    // cellから辿り着けるマス
+    pub fn reachable_from(&self, cell: Cell) -> Vec<bool> {
+        let mut reached = vec![false; self.tiles.len()];
+        if self.is_wall(cell) {
+            return reached;
+        }
+
+        let mut queue = VecDeque::from([cell]);
+        reached[self.index(cell)] = true;
+
+        while let Some(cell) = queue.pop_front() {
+            for next in cell.cross() {
+                if !self.is_wall(next) && !reached[self.index(next)] {
+                    reached[self.index(next)] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        reached
+    }
+
+    // 全ての通路がstartsのどこからでも辿り着けるか
+    pub fn is_connected(&self, starts: &[Cell]) -> bool {
+        starts.iter().all(|&start| {
+            let reached = self.reachable_from(start);
+            self.passages().all(|cell| reached[self.index(cell)])
+        })
+    }
+
+    // from から to が見えるか (間に壁が無いか)
+    // マスの中心同士を結んだ線が通るマスを調べ、斜めに進む時は壁の角の隙間も通さない
+    pub fn can_see(&self, from: Cell, to: Cell) -> bool {
+        let (dx, dy) = ((to.x - from.x).abs(), -(to.y - from.y).abs());
+        let (sx, sy) = ((to.x - from.x).signum(), (to.y - from.y).signum());
+        let mut error = dx + dy;
+        let mut current = from;
+
+        while current != to {
+            if current != from && self.is_wall(current) {
+                return false;
+            }
+
+            let (step_x, step_y) = (2 * error >= dy, 2 * error <= dx);
+            if step_x
+                && step_y
+                && self.is_wall(Cell::new(current.x + sx, current.y))
+                && self.is_wall(Cell::new(current.x, current.y + sy))
+            {
+                return false;
+            }
+            if step_x {
+                error += dy;
+                current.x += sx;
+            }
+            if step_y {
+                error += dx;
+                current.y += sy;
+            }
+        }
+        true
+    }
+
+    // cell に一番近い通路 (直線距離)
+    pub fn nearest_passage(&self, cell: Cell) -> Option<Cell> {
+        self.passages()
+            .min_by_key(|p| (p.x - cell.x).pow(2) + (p.y - cell.y).pow(2))
+    }
+
+    const fn index(&self, cell: Cell) -> usize {
+        (cell.y * self.width + cell.x) as usize
+    }
+}
+
+// 壁を '#'、通路を '.' で表示する (上の行から)
+impl fmt::Display for Maze {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let c = match self.tile(Cell::new(x, y)) {
+                    Tile::Wall => '#',
+                    Tile::Passage => '.',
+                };
+                write!(f, "{c}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    // ゲームと同じ四隅の初期位置
+    fn starts(maze: &Maze) -> [Cell; 4] {
+        let (right, top) = (maze.width - 2, maze.height - 2);
+        [
+            Cell::new(1, top),
+            Cell::new(right, 1),
+            Cell::new(right, top),
+            Cell::new(1, 1),
+        ]
+    }
+
+    #[test]
+    fn every_passage_is_reachable_from_spawns() {
+        for seed in 0..200 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            for (width, height) in [(5, 5), (7, 9), (15, 15), (21, 13)] {
+                let maze = Maze::stick_knock_down(width, height, &mut rng);
+                let starts = starts(&maze);
+                assert!(
+                    starts.iter().all(|&start| !maze.is_wall(start)),
+                    "初期位置が壁です (seed {seed})\n{maze}"
+                );
+                assert!(
+                    maze.is_connected(&starts),
+                    "辿り着けない通路があります (seed {seed})\n{maze}"
+                );
+            }
+        }
+    }
+}
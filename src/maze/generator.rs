@@ -0,0 +1,494 @@
+use rand::{seq::SliceRandom, Rng, RngCore};
+
+use super::{Cell, Maze, Tile};
+
+// 迷路の生成アルゴリズム
+pub trait MazeGenerator {
+    fn generate(&self, width: i32, height: i32, rng: &mut dyn RngCore) -> Maze;
+}
+
+// 設定で選べるアルゴリズム
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    #[default]
+    StickKnockDown,
+    RecursiveBacktracker,
+    Prim,
+    Kruskal,
+    Wilson,
+    Eller,
+}
+
+impl Algorithm {
+    const ALL: [Algorithm; 6] = [
+        Algorithm::StickKnockDown,
+        Algorithm::RecursiveBacktracker,
+        Algorithm::Prim,
+        Algorithm::Kruskal,
+        Algorithm::Wilson,
+        Algorithm::Eller,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Algorithm::StickKnockDown => "Stick",
+            Algorithm::RecursiveBacktracker => "Backtracker",
+            Algorithm::Prim => "Prim",
+            Algorithm::Kruskal => "Kruskal",
+            Algorithm::Wilson => "Wilson",
+            Algorithm::Eller => "Eller",
+        }
+    }
+
+    pub const fn generator(self) -> &'static dyn MazeGenerator {
+        match self {
+            Algorithm::StickKnockDown => &StickKnockDown,
+            Algorithm::RecursiveBacktracker => &RecursiveBacktracker,
+            Algorithm::Prim => &Prim,
+            Algorithm::Kruskal => &Kruskal,
+            Algorithm::Wilson => &Wilson,
+            Algorithm::Eller => &Eller,
+        }
+    }
+
+    // 次のアルゴリズム (最後の次は最初に戻る)
+    pub fn next(self) -> Algorithm {
+        let index = Self::ALL.iter().position(|&a| a == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn prev(self) -> Algorithm {
+        let index = Self::ALL.iter().position(|&a| a == self).unwrap_or(0);
+        Self::ALL[(index + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+// 棒倒し法
+pub struct StickKnockDown;
+
+// 穴掘り法 (深さ優先探索)
+pub struct RecursiveBacktracker;
+
+// プリム法
+pub struct Prim;
+
+// クラスカル法
+pub struct Kruskal;
+
+// ウィルソン法 (一様全域木)
+pub struct Wilson;
+
+// エラー法 (一行ずつ作る)
+pub struct Eller;
+
+impl MazeGenerator for StickKnockDown {
+    fn generate(&self, width: i32, height: i32, rng: &mut dyn RngCore) -> Maze {
+        Maze::stick_knock_down(width, height, rng)
+    }
+}
+
+impl MazeGenerator for RecursiveBacktracker {
+    fn generate(&self, width: i32, height: i32, rng: &mut dyn RngCore) -> Maze {
+        let mut maze = Maze::filled(width, height);
+        let rooms = Rooms::new(&maze);
+        let mut visited = vec![false; rooms.len()];
+
+        let start = rooms.random(rng);
+        maze.set(start, Tile::Passage);
+        visited[rooms.index(start)] = true;
+        let mut stack = vec![start];
+
+        while let Some(&current) = stack.last() {
+            let unvisited: Vec<Cell> = rooms
+                .neighbors(current)
+                .filter(|&next| !visited[rooms.index(next)])
+                .collect();
+
+            match unvisited.choose(rng) {
+                Some(&next) => {
+                    maze.carve(current, next);
+                    visited[rooms.index(next)] = true;
+                    stack.push(next);
+                }
+                // 行き止まりなら戻る
+                None => {
+                    stack.pop();
+                }
+            }
+        }
+
+        maze
+    }
+}
+
+impl MazeGenerator for Prim {
+    fn generate(&self, width: i32, height: i32, rng: &mut dyn RngCore) -> Maze {
+        let mut maze = Maze::filled(width, height);
+        let rooms = Rooms::new(&maze);
+        let mut visited = vec![false; rooms.len()];
+
+        let start = rooms.random(rng);
+        maze.set(start, Tile::Passage);
+        visited[rooms.index(start)] = true;
+        let mut frontier: Vec<Cell> = rooms.neighbors(start).collect();
+
+        while !frontier.is_empty() {
+            let room = frontier.swap_remove(rng.gen_range(0..frontier.len()));
+            if visited[rooms.index(room)] {
+                continue;
+            }
+
+            // 既に迷路になっている隣の部屋とつなぐ
+            let connected: Vec<Cell> = rooms
+                .neighbors(room)
+                .filter(|&next| visited[rooms.index(next)])
+                .collect();
+            let &next = connected.choose(rng).expect("choose エラー");
+            maze.carve(room, next);
+            visited[rooms.index(room)] = true;
+
+            frontier.extend(rooms.neighbors(room).filter(|&n| !visited[rooms.index(n)]));
+        }
+
+        maze
+    }
+}
+
+impl MazeGenerator for Kruskal {
+    fn generate(&self, width: i32, height: i32, rng: &mut dyn RngCore) -> Maze {
+        let mut maze = Maze::filled(width, height);
+        let rooms = Rooms::new(&maze);
+        let mut sets = UnionFind::new(rooms.len());
+
+        // 隣り合う部屋の組
+        let mut edges: Vec<(Cell, Cell)> = Vec::new();
+        for room in rooms.iter() {
+            maze.set(room, Tile::Passage);
+            for next in rooms.neighbors(room) {
+                if (next.x, next.y) > (room.x, room.y) {
+                    edges.push((room, next));
+                }
+            }
+        }
+        edges.shuffle(rng);
+
+        for (a, b) in edges {
+            if sets.union(rooms.index(a), rooms.index(b)) {
+                maze.carve(a, b);
+            }
+        }
+
+        maze
+    }
+}
+
+impl MazeGenerator for Wilson {
+    fn generate(&self, width: i32, height: i32, rng: &mut dyn RngCore) -> Maze {
+        let mut maze = Maze::filled(width, height);
+        let rooms = Rooms::new(&maze);
+        let mut in_maze = vec![false; rooms.len()];
+        // ランダムウォークで最後に進んだ方向
+        let mut walk: Vec<Option<Cell>> = vec![None; rooms.len()];
+
+        let start = rooms.random(rng);
+        maze.set(start, Tile::Passage);
+        in_maze[rooms.index(start)] = true;
+
+        let mut order: Vec<Cell> = rooms.iter().collect();
+        order.shuffle(rng);
+
+        for room in order {
+            // 迷路に当たるまで歩く (ループは上書きで消える)
+            let mut current = room;
+            while !in_maze[rooms.index(current)] {
+                let neighbors: Vec<Cell> = rooms.neighbors(current).collect();
+                let &next = neighbors.choose(rng).expect("choose エラー");
+                walk[rooms.index(current)] = Some(next);
+                current = next;
+            }
+
+            // 歩いた道を掘る
+            let mut current = room;
+            while !in_maze[rooms.index(current)] {
+                let next = walk[rooms.index(current)].expect("walk エラー");
+                maze.carve(current, next);
+                in_maze[rooms.index(current)] = true;
+                current = next;
+            }
+        }
+
+        maze
+    }
+}
+
+impl MazeGenerator for Eller {
+    fn generate(&self, width: i32, height: i32, rng: &mut dyn RngCore) -> Maze {
+        let mut maze = Maze::filled(width, height);
+        let rooms = Rooms::new(&maze);
+        // 今の行の各列が属する集合 (0 は未所属)
+        let mut sets = vec![0; rooms.cols as usize];
+        let mut next_set = 1;
+
+        for row in 0..rooms.rows {
+            let last_row = row == rooms.rows - 1;
+
+            for set in &mut sets {
+                if *set == 0 {
+                    *set = next_set;
+                    next_set += 1;
+                }
+            }
+
+            // 横につなぐ (最後の行は全てつなぐ)
+            for col in 0..rooms.cols as usize - 1 {
+                if sets[col] != sets[col + 1] && (last_row || rng.gen_bool(0.5)) {
+                    maze.carve(rooms.cell(col as i32, row), rooms.cell(col as i32 + 1, row));
+                    let (old, new) = (sets[col + 1], sets[col]);
+                    for set in &mut sets {
+                        if *set == old {
+                            *set = new;
+                        }
+                    }
+                }
+            }
+            for col in 0..rooms.cols {
+                maze.set(rooms.cell(col, row), Tile::Passage);
+            }
+            if last_row {
+                break;
+            }
+
+            // 縦につなぐ (集合ごとに少なくとも一つ)
+            let mut next_sets = vec![0; sets.len()];
+            let mut members: Vec<usize> = (0..sets.len()).collect();
+            members.shuffle(rng);
+            for &col in &members {
+                let set = sets[col];
+                let first = !next_sets.contains(&set);
+                if first || rng.gen_bool(0.3) {
+                    maze.carve(rooms.cell(col as i32, row), rooms.cell(col as i32, row + 1));
+                    next_sets[col] = set;
+                }
+            }
+            sets = next_sets;
+        }
+
+        maze
+    }
+}
+
+// 奇数座標のマスを部屋として扱う
+struct Rooms {
+    cols: i32,
+    rows: i32,
+}
+
+impl Rooms {
+    fn new(maze: &Maze) -> Rooms {
+        Rooms {
+            cols: ((maze.width - 1) / 2).max(1),
+            rows: ((maze.height - 1) / 2).max(1),
+        }
+    }
+
+    fn len(&self) -> usize {
+        (self.cols * self.rows) as usize
+    }
+
+    fn cell(&self, col: i32, row: i32) -> Cell {
+        Cell::new(col * 2 + 1, row * 2 + 1)
+    }
+
+    fn index(&self, room: Cell) -> usize {
+        ((room.y / 2) * self.cols + room.x / 2) as usize
+    }
+
+    fn contains(&self, room: Cell) -> bool {
+        room.x > 0 && room.y > 0 && room.x / 2 < self.cols && room.y / 2 < self.rows
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Cell> + '_ {
+        (0..self.rows).flat_map(move |row| (0..self.cols).map(move |col| self.cell(col, row)))
+    }
+
+    fn random(&self, rng: &mut dyn RngCore) -> Cell {
+        self.cell(rng.gen_range(0..self.cols), rng.gen_range(0..self.rows))
+    }
+
+    fn neighbors(&self, room: Cell) -> impl Iterator<Item = Cell> + '_ {
+        [
+            Cell::new(room.x, room.y + 2),
+            Cell::new(room.x + 2, room.y),
+            Cell::new(room.x, room.y - 2),
+            Cell::new(room.x - 2, room.y),
+        ]
+        .into_iter()
+        .filter(|&next| self.contains(next))
+    }
+}
+
+struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> UnionFind {
+        UnionFind {
+            parent: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        let mut root = i;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        // 経路圧縮
+        let mut i = i;
+        while self.parent[i] != root {
+            let next = self.parent[i];
+            self.parent[i] = root;
+            i = next;
+        }
+        root
+    }
+
+    // 別の集合だったら結合して true を返す
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        self.parent[b] = a;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    // 奇数の大きさ (5x5 まで)
+    const SIZES: [(i32, i32); 6] = [(5, 5), (5, 9), (9, 5), (7, 11), (13, 7), (21, 21)];
+
+    // 隣り合う通路の組の数
+    fn passage_edges(maze: &Maze) -> usize {
+        maze.passages()
+            .flat_map(|cell| [Cell::new(cell.x + 1, cell.y), Cell::new(cell.x, cell.y + 1)])
+            .filter(|&next| !maze.is_wall(next))
+            .count()
+    }
+
+    fn is_connected(maze: &Maze) -> bool {
+        let start = maze.passages().next().expect("通路がありません");
+        maze.is_connected(&[start])
+    }
+
+    // ループが無い (通路の組の数 == 通路の数 - 1)
+    fn is_perfect(maze: &Maze) -> bool {
+        passage_edges(maze) == maze.passages().count() - 1
+    }
+
+    // (アルゴリズム, 幅, 高さ, 迷路) を全て作る
+    fn mazes(seed: u64) -> impl Iterator<Item = (Algorithm, i32, i32, Maze)> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Algorithm::ALL.into_iter().flat_map(move |algorithm| {
+            SIZES
+                .into_iter()
+                .map(|(width, height)| {
+                    let maze = algorithm.generator().generate(width, height, &mut rng);
+                    (algorithm, width, height, maze)
+                })
+                .collect::<Vec<_>>()
+        })
+    }
+
+    #[test]
+    fn generated_mazes_are_connected_and_perfect() {
+        for seed in 0..30 {
+            for (algorithm, width, height, maze) in mazes(seed) {
+                assert_eq!((maze.width, maze.height), (width, height));
+                assert!(
+                    is_connected(&maze),
+                    "{algorithm:?} {width}x{height} seed {seed}\n{maze}"
+                );
+                assert!(
+                    is_perfect(&maze),
+                    "{algorithm:?} {width}x{height} seed {seed}\n{maze}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn two_player_goal_is_equidistant() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for seed in 0..30 {
+            for (algorithm, width, height, mut maze) in mazes(seed) {
+                for braid in [0., 0.5] {
+                    maze.braid(braid, &mut rng);
+                    // ゲームと同じ左上と右下
+                    let starts = [Cell::new(1, height - 2), Cell::new(width - 2, 1)];
+                    let (goal, gap) = maze
+                        .fair_goal(&starts, &mut rng)
+                        .expect("ゴールがありません");
+
+                    let distances: Vec<_> = starts
+                        .iter()
+                        .map(|&start| maze.distances_from(start).get(goal))
+                        .collect();
+                    assert_eq!(gap, 0, "{algorithm:?} {width}x{height} seed {seed}\n{maze}");
+                    assert_eq!(
+                        distances[0], distances[1],
+                        "{algorithm:?} {width}x{height} seed {seed}\n{maze}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn braided_mazes_stay_connected() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for seed in 0..30 {
+            for (algorithm, width, height, maze) in mazes(seed) {
+                // 壊さなければ元のまま
+                let mut unchanged = maze.clone();
+                unchanged.braid(0., &mut rng);
+                assert_eq!(unchanged, maze);
+
+                for ratio in [0.3, 1.] {
+                    let mut braided = maze.clone();
+                    braided.braid(ratio, &mut rng);
+                    assert!(
+                        is_connected(&braided),
+                        "{algorithm:?} {width}x{height} seed {seed} braid {ratio}\n{braided}"
+                    );
+                    assert!(passage_edges(&braided) >= passage_edges(&maze));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn symmetrized_mazes_are_connected_and_symmetric() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for seed in 0..30 {
+            for (algorithm, width, height, mut maze) in mazes(seed) {
+                maze.braid(0.5, &mut rng);
+                maze.symmetrize(&mut rng);
+                assert!(
+                    is_connected(&maze),
+                    "{algorithm:?} {width}x{height} seed {seed}\n{maze}"
+                );
+                assert!(
+                    maze.cells()
+                        .all(|cell| maze.tile(cell) == maze.tile(maze.rotated(cell))),
+                    "{algorithm:?} {width}x{height} seed {seed}\n{maze}"
+                );
+            }
+        }
+    }
+}
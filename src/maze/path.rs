@@ -0,0 +1,292 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, VecDeque},
+};
+
+use rand::{seq::SliceRandom, Rng};
+
+use super::{Cell, Maze};
+
+// あるマスから各マスまでの最短距離
+pub struct DistanceMap {
+    width: i32,
+    height: i32,
+    distances: Vec<Option<u32>>,
+}
+
+impl DistanceMap {
+    // 辿り着けないマスは None
+    pub fn get(&self, cell: Cell) -> Option<u32> {
+        if cell.x < 0 || cell.y < 0 || cell.x >= self.width || cell.y >= self.height {
+            return None;
+        }
+        self.distances[(cell.y * self.width + cell.x) as usize]
+    }
+}
+
+impl Maze {
+    // 幅優先探索で start からの距離を求める
+    pub fn distances_from(&self, start: Cell) -> DistanceMap {
+        let mut distances = vec![None; self.tiles.len()];
+
+        if !self.is_wall(start) {
+            distances[self.index(start)] = Some(0);
+            let mut queue = VecDeque::from([start]);
+
+            while let Some(cell) = queue.pop_front() {
+                let distance = distances[self.index(cell)].unwrap_or(0);
+                for next in cell.cross() {
+                    if !self.is_wall(next) && distances[self.index(next)].is_none() {
+                        distances[self.index(next)] = Some(distance + 1);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        DistanceMap {
+            width: self.width,
+            height: self.height,
+            distances,
+        }
+    }
+
+    // from から to までの最短経路 (from と to を含む)
+    pub fn shortest_path(&self, from: Cell, to: Cell) -> Option<Vec<Cell>> {
+        self.path_to_nearest(from, &[to])
+    }
+
+    // from から targets のうち一番近いマスまでの最短経路 (from と到着したマスを含む)
+    pub fn path_to_nearest(&self, from: Cell, targets: &[Cell]) -> Option<Vec<Cell>> {
+        if self.is_wall(from) {
+            return None;
+        }
+
+        // 一つ前のマス
+        let mut previous: Vec<Option<Cell>> = vec![None; self.tiles.len()];
+        let mut visited = vec![false; self.tiles.len()];
+        visited[self.index(from)] = true;
+        let mut queue = VecDeque::from([from]);
+
+        while let Some(cell) = queue.pop_front() {
+            if targets.contains(&cell) {
+                return Some(self.trace_back(&previous, cell));
+            }
+
+            for next in cell.cross() {
+                if !self.is_wall(next) && !visited[self.index(next)] {
+                    visited[self.index(next)] = true;
+                    previous[self.index(next)] = Some(cell);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+
+    // from から to までの、通るマスの重みの合計が一番小さい経路 (from と to を含む)
+    // cost は入るマスの重みで、1 以上にする
+    pub fn weighted_path(
+        &self,
+        from: Cell,
+        to: Cell,
+        cost: impl Fn(Cell) -> u32,
+    ) -> Option<Vec<Cell>> {
+        if self.is_wall(from) {
+            return None;
+        }
+
+        let mut previous: Vec<Option<Cell>> = vec![None; self.tiles.len()];
+        let mut totals: Vec<Option<u32>> = vec![None; self.tiles.len()];
+        totals[self.index(from)] = Some(0);
+        let mut heap = BinaryHeap::from([Reverse((0, from.y, from.x))]);
+
+        while let Some(Reverse((total, y, x))) = heap.pop() {
+            let cell = Cell::new(x, y);
+            if cell == to {
+                return Some(self.trace_back(&previous, cell));
+            }
+            // もっと近い経路で先に調べてある
+            if totals[self.index(cell)].is_some_and(|best| best < total) {
+                continue;
+            }
+
+            for next in cell.cross() {
+                if self.is_wall(next) {
+                    continue;
+                }
+                let next_total = total + cost(next);
+                if totals[self.index(next)].is_none_or(|best| next_total < best) {
+                    totals[self.index(next)] = Some(next_total);
+                    previous[self.index(next)] = Some(cell);
+                    heap.push(Reverse((next_total, next.y, next.x)));
+                }
+            }
+        }
+
+        None
+    }
+
+    // 来た道を戻って経路にする
+    fn trace_back(&self, previous: &[Option<Cell>], cell: Cell) -> Vec<Cell> {
+        let mut path = vec![cell];
+        let mut current = cell;
+        while let Some(prev) = previous[self.index(current)] {
+            path.push(prev);
+            current = prev;
+        }
+        path.reverse();
+        path
+    }
+
+    // starts の全てのマスからの最短距離が同じマスのうち、一番遠いマスと距離の差 (0)
+    // 同じ距離のマスが無ければ、距離の差が一番小さいマスとその差
+    pub fn fair_goal<R: Rng + ?Sized>(&self, starts: &[Cell], rng: &mut R) -> Option<(Cell, u32)> {
+        let maps: Vec<DistanceMap> = starts
+            .iter()
+            .map(|&start| self.distances_from(start))
+            .collect();
+
+        // (距離の差, 距離の符号反転) が小さいほど良い
+        let score = |cell: Cell| {
+            let distances: Option<Vec<u32>> = maps.iter().map(|map| map.get(cell)).collect();
+            let distances = distances?;
+            let (&min, &max) = (distances.iter().min()?, distances.iter().max()?);
+            Some((max - min, -(max as i64)))
+        };
+
+        let best = self.passages().filter_map(score).min()?;
+        let candidates: Vec<Cell> = self
+            .passages()
+            .filter(|&cell| score(cell) == Some(best))
+            .collect();
+        candidates.choose(rng).map(|&cell| (cell, best.0))
+    }
+}
+
+impl Maze {
+    // starts の各マスからの道のりの合計が釣り合うように、二つずつ組にして通路を並べる
+    // ランダムに選んだマスの後に、一人目との距離の差が逆になるマスを続ける
+    // (2 人なら、それぞれから a と b のマスに、b と a のマスを組にする)
+    // 組ごとに釣り合うので、先頭から偶数個取ればどこで切っても差は無い
+    pub fn balanced_passages<R: Rng + ?Sized>(&self, starts: &[Cell], rng: &mut R) -> Vec<Cell> {
+        let maps: Vec<DistanceMap> = starts
+            .iter()
+            .map(|&start| self.distances_from(start))
+            .collect();
+
+        // 最初のマスからの距離と、他のマスからの距離の差
+        let mut rest: Vec<(Cell, Vec<i64>)> = self
+            .passages()
+            .filter_map(|cell| {
+                let distances: Option<Vec<i64>> =
+                    maps.iter().map(|map| Some(map.get(cell)? as i64)).collect();
+                let distances = distances?;
+                let first = *distances.first()?;
+                Some((cell, distances[1..].iter().map(|d| d - first).collect()))
+            })
+            .collect();
+        rest.shuffle(rng);
+
+        // 差ごとのまだ使っていないマス
+        let mut groups: HashMap<Vec<i64>, Vec<Cell>> = HashMap::new();
+        for (cell, diff) in &rest {
+            groups.entry(diff.clone()).or_default().push(*cell);
+        }
+
+        // ちょうど打ち消すマスがあれば組にして先に並べる
+        let mut cells = Vec::with_capacity(rest.len());
+        let mut leftover: Vec<(Cell, Vec<i64>)> = Vec::new();
+        for (cell, diff) in rest {
+            let group = groups.entry(diff.clone()).or_default();
+            // 既に組になっている
+            let Some(index) = group.iter().position(|&c| c == cell) else {
+                continue;
+            };
+            group.swap_remove(index);
+
+            let opposite: Vec<i64> = diff.iter().map(|d| -d).collect();
+            match groups.get_mut(&opposite).and_then(Vec::pop) {
+                Some(partner) => cells.extend([cell, partner]),
+                None => leftover.push((cell, diff)),
+            }
+        }
+
+        // 残りは差が一番小さくなるマスと組にする (3 人以上だと多く残る)
+        let norm =
+            |a: &[i64], b: &[i64]| -> i64 { a.iter().zip(b).map(|(a, b)| (a + b).abs()).sum() };
+        while let Some((cell, diff)) = leftover.pop() {
+            cells.push(cell);
+            let partner = (0..leftover.len()).min_by_key(|&i| norm(&diff, &leftover[i].1));
+            if let Some(index) = partner {
+                cells.push(leftover.swap_remove(index).0);
+            }
+        }
+
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn balanced_pairs_have_equal_totals() {
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            for (width, height) in [(11, 11), (15, 15), (21, 13)] {
+                let mut maze = Maze::stick_knock_down(width, height, &mut rng);
+                maze.braid(0.3, &mut rng);
+                // ゲームと同じ左上と右下
+                let starts = [Cell::new(1, height - 2), Cell::new(width - 2, 1)];
+                let cells = maze.balanced_passages(&starts, &mut rng);
+                assert_eq!(cells.len(), maze.passages().count());
+
+                let maps: Vec<DistanceMap> = starts
+                    .iter()
+                    .map(|&start| maze.distances_from(start))
+                    .collect();
+                for count in [2, 4, 8, 12] {
+                    let totals: Vec<u32> = maps
+                        .iter()
+                        .map(|map| cells[..count].iter().filter_map(|&c| map.get(c)).sum())
+                        .collect();
+                    assert_eq!(
+                        totals[0],
+                        totals[1],
+                        "{count} 個 (seed {seed}) {:?}\n{maze}",
+                        &cells[..count]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn weighted_path_is_cheapest() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut maze = Maze::stick_knock_down(15, 15, &mut rng);
+        maze.braid(0.5, &mut rng);
+        let (from, to) = (Cell::new(1, 13), Cell::new(13, 1));
+        let shortest = maze.shortest_path(from, to).unwrap();
+
+        // 重みが全部同じなら最短経路と同じ長さ
+        let path = maze.weighted_path(from, to, |_| 1).unwrap();
+        assert_eq!(path.len(), shortest.len());
+
+        // 重みを付けると、最短経路より重みの合計が大きくならない
+        let cost = |cell: Cell| 1 + (cell.x * 7 + cell.y * 3).rem_euclid(4) as u32;
+        let total = |path: &[Cell]| path[1..].iter().map(|&cell| cost(cell)).sum::<u32>();
+        let path = maze.weighted_path(from, to, cost).unwrap();
+        assert_eq!((path[0], path[path.len() - 1]), (from, to));
+        for pair in path.windows(2) {
+            assert!(!maze.is_wall(pair[1]));
+            assert!(pair[0].cross().contains(&pair[1]));
+        }
+        assert!(total(&path) <= total(&shortest));
+    }
+}
@@ -0,0 +1,210 @@
+use bevy::prelude::*;
+
+use crate::{ai::Bot, controls::PlayerInput, Controls, Effects, Game, GridPos, Labyrinth, Player};
+
+// キーを離してからも向きを覚えておく時間 (秒)
+const BUFFER_TIME: f32 = 0.2;
+
+// 入力の向きとみなすスティックの倒し具合
+const INPUT_THRESHOLD: f32 = 0.3;
+
+// プレイヤーの動き方
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    // ピクセル単位で自由に動く
+    #[default]
+    Free,
+    // マスからマスへ動く
+    Grid,
+}
+
+// マス移動の状態
+#[derive(Component, Debug, Default)]
+pub struct GridMove {
+    // 移動中のマス (止まっている時は from == to)
+    from: IVec2,
+    to: IVec2,
+    // from から to までの進み具合 (0.0 ~ 1.0)
+    progress: f32,
+    // 最後に進んだ向き
+    direction: IVec2,
+    // 先行入力された向きと、その残り時間
+    buffered: IVec2,
+    buffer_left: f32,
+}
+
+impl Movement {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Movement::Free => "Free",
+            Movement::Grid => "Grid",
+        }
+    }
+
+    pub const fn toggle(self) -> Movement {
+        match self {
+            Movement::Free => Movement::Grid,
+            Movement::Grid => Movement::Free,
+        }
+    }
+}
+
+impl GridMove {
+    fn is_resting(&self) -> bool {
+        self.from == self.to
+    }
+
+    // 先行入力から次に進む向きの候補を並べる
+    // 斜めに入力した時は、今と違う向きに曲がるのを先に試す
+    fn candidates(&self) -> Vec<IVec2> {
+        let (x, y) = (
+            IVec2::new(self.buffered.x, 0),
+            IVec2::new(0, self.buffered.y),
+        );
+        let mut candidates: Vec<IVec2> = match (x != IVec2::ZERO, y != IVec2::ZERO) {
+            (true, true) if self.direction.x != 0 => vec![y, x],
+            (true, true) => vec![x, y],
+            (true, false) => vec![x],
+            (false, true) => vec![y],
+            (false, false) => vec![],
+        };
+        // 曲がれない間は今の向きに進み続ける
+        if !candidates.is_empty() && self.direction != IVec2::ZERO {
+            candidates.push(self.direction);
+        }
+        candidates
+    }
+}
+
+// 入力をマスの向き (x, y はそれぞれ -1, 0, 1) にする
+fn input_to_grid(direction: Vec2) -> IVec2 {
+    let axis = |value: f32| {
+        if value.abs() < INPUT_THRESHOLD {
+            0
+        } else {
+            value.signum() as i32
+        }
+    };
+    IVec2::new(axis(direction.x), axis(direction.y))
+}
+
+// すり抜けの効果に合わせて、プレイヤーがいてよい位置に直す
+// すり抜け中は外周の壁の内側に収め、すり抜けが終わった時に壁の中にいたら一番近い通路に出す
+pub fn confine_to_maze(pos: Vec2, effects: &Effects, labyrinth: &Labyrinth, game: &Game) -> Vec2 {
+    if effects.can_pass_wall() {
+        let min = game.grid_to_world(IVec2::ONE);
+        let max = game.grid_to_world(IVec2::new(game.maze_width() - 2, game.maze_height() - 2));
+        return pos.clamp(min, max);
+    }
+
+    let cell = game.world_to_grid(pos);
+    if labyrinth.0.is_wall(cell.into()) {
+        if let Some(passage) = labyrinth.0.nearest_passage(cell.into()) {
+            return game.grid_to_world(passage.into());
+        }
+    }
+    pos
+}
+
+// マスからマスへ動かす
+// 入力は少しの間覚えておくので、曲がり角の手前で押しても曲がれる
+pub fn move_player_grid(
+    mut player_query: Query<(
+        &mut Transform,
+        &mut GridMove,
+        &GridPos,
+        &Effects,
+        &Controls,
+        &Player,
+        Option<&Bot>,
+    )>,
+    input: PlayerInput,
+    labyrinth: Res<Labyrinth>,
+    game: Res<Game>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+
+    for (mut transform, mut grid_move, grid_pos, effects, controls, player, bot) in
+        &mut player_query
+    {
+        let grid_move = &mut *grid_move;
+
+        let direction = input_to_grid(match bot {
+            Some(bot) => bot.direction,
+            None => input.direction(controls, player),
+        });
+        if direction != IVec2::ZERO {
+            grid_move.buffered = direction;
+            grid_move.buffer_left = BUFFER_TIME;
+        } else {
+            grid_move.buffer_left -= delta;
+            if grid_move.buffer_left <= 0. {
+                grid_move.buffered = IVec2::ZERO;
+            }
+        }
+
+        // 止まっている時は今いるマスから動き出す (自由移動から切り替えた時など)
+        if grid_move.is_resting() {
+            let pos = confine_to_maze(game.grid_to_world(grid_pos.0), effects, &labyrinth, &game);
+            grid_move.from = game.world_to_grid(pos);
+            grid_move.to = grid_move.from;
+            grid_move.progress = 0.;
+        }
+
+        let passable = |pos: IVec2| {
+            if effects.can_pass_wall() {
+                let center = game.grid_to_world(pos);
+                confine_to_maze(center, effects, &labyrinth, &game) == center
+            } else {
+                !labyrinth.0.is_wall(pos.into())
+            }
+        };
+
+        // 逆向きにはマスの途中でも戻れる
+        if !grid_move.is_resting() && grid_move.buffered == -grid_move.direction {
+            std::mem::swap(&mut grid_move.from, &mut grid_move.to);
+            grid_move.progress = 1. - grid_move.progress;
+            grid_move.direction = -grid_move.direction;
+        }
+
+        if !effects.is_frozen() {
+            // 一秒に進むマスの数
+            let speed = game.player_speed / game.block_size.x * effects.speed_rate();
+            let mut remaining = speed * delta;
+
+            loop {
+                if grid_move.is_resting() {
+                    let next = grid_move
+                        .candidates()
+                        .into_iter()
+                        .find(|&direction| passable(grid_move.from + direction));
+                    let Some(next) = next else {
+                        grid_move.direction = IVec2::ZERO;
+                        break;
+                    };
+                    grid_move.to = grid_move.from + next;
+                    grid_move.direction = next;
+                }
+
+                let step = (1. - grid_move.progress).min(remaining);
+                grid_move.progress += step;
+                remaining -= step;
+
+                // マスに着いたら、残りの分で次のマスに進む
+                if grid_move.progress >= 1. {
+                    grid_move.from = grid_move.to;
+                    grid_move.progress = 0.;
+                }
+                if remaining <= 0. {
+                    break;
+                }
+            }
+        }
+
+        let from = game.grid_to_world(grid_move.from);
+        let to = game.grid_to_world(grid_move.to);
+        let pos = from.lerp(to, grid_move.progress);
+        transform.translation = pos.extend(transform.translation.z);
+    }
+}
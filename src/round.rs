@@ -0,0 +1,402 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    item::ItemSpawner, ButtonAction, Game, Goal, GridPos, Item, Player, RoundRules, ScoreBoard,
+    ScoreEvent, Status, SCOREBOARD_TEXT_PADDING, TEXT_COLOR,
+};
+
+// 結果画面
+#[derive(Component)]
+pub struct ResultScreen;
+
+#[derive(Component)]
+pub struct MatchText;
+
+#[derive(Component)]
+pub struct TimerText;
+
+// ラウンドの経過時間
+#[derive(Resource, Default)]
+pub struct RoundTimer {
+    pub elapsed: f32,
+}
+
+impl RoundTimer {
+    // 制限時間の残り (制限時間なしなら None)
+    pub fn remaining(&self, time_limit: u32) -> Option<f32> {
+        (time_limit > 0).then(|| (time_limit as f32 - self.elapsed).max(0.))
+    }
+
+    pub fn is_up(&self, time_limit: u32) -> bool {
+        self.remaining(time_limit) == Some(0.)
+    }
+}
+
+// 試合全体の成績 (ラウンドをまたいで残る)
+#[derive(Resource, Default)]
+pub struct MatchScore {
+    // プレイヤーの id ごとの勝ったラウンドの数
+    pub wins: BTreeMap<usize, usize>,
+    // 終わったラウンドの数 (引き分けも含む)
+    pub rounds: usize,
+}
+
+impl MatchScore {
+    // 試合に勝ったプレイヤーの id
+    // 過半数のラウンドを取るか、全ラウンドが終わった時点で一番多く勝っている方
+    // 決着がついていない、または引き分けなら None
+    pub fn winner(&self, rounds: usize) -> Option<usize> {
+        let needed = rounds / 2 + 1;
+        if let Some((&id, _)) = self.wins.iter().find(|&(_, &wins)| wins >= needed) {
+            Some(id)
+        } else if self.rounds >= rounds {
+            leader(&self.wins)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_over(&self, rounds: usize) -> bool {
+        self.rounds >= rounds || self.winner(rounds).is_some()
+    }
+}
+
+impl ScoreBoard {
+    // 勝ったプレイヤーの id (引き分けなら None)
+    pub fn winner(&self) -> Option<usize> {
+        leader(&self.scores)
+    }
+}
+
+// 一人だけ一番多いプレイヤーの id
+fn leader(scores: &BTreeMap<usize, usize>) -> Option<usize> {
+    let &max = scores.values().max()?;
+    let mut leaders = scores.iter().filter(|&(_, &score)| score == max);
+    match (leaders.next(), leaders.next()) {
+        (Some((&id, _)), None) if max > 0 => Some(id),
+        _ => None,
+    }
+}
+
+// "1 - 0 - 2" の形で並べる
+fn scores_text(scores: &BTreeMap<usize, usize>, player_num: usize) -> String {
+    (0..player_num)
+        .map(|id| scores.get(&id).copied().unwrap_or(0).to_string())
+        .collect::<Vec<_>>()
+        .join(" - ")
+}
+
+// 新しいラウンドが始まったらスコアを戻す
+pub fn reset_score_board(mut score_board: ResMut<ScoreBoard>, mut event: EventWriter<ScoreEvent>) {
+    *score_board = ScoreBoard::default();
+    event.send_default();
+}
+
+pub fn reset_round_timer(mut timer: ResMut<RoundTimer>) {
+    *timer = RoundTimer::default();
+}
+
+// 制限時間になったらラウンド終了 (スコアが多い方の勝ち)
+pub fn round_timer_tick(
+    mut timer: ResMut<RoundTimer>,
+    game: Res<Game>,
+    time: Res<Time>,
+    mut state: ResMut<NextState<Status>>,
+) {
+    timer.elapsed += time.delta_seconds();
+    if timer.is_up(game.time_limit) {
+        state.set(Status::RoundOver);
+    }
+}
+
+// アイテムが全て出て、全て取られたらラウンド終了
+pub fn round_end_check(
+    item_query: Query<(), With<Item>>,
+    spawner: Res<ItemSpawner>,
+    rules: Res<RoundRules>,
+    mut state: ResMut<NextState<Status>>,
+) {
+    if item_query.is_empty() && spawner.is_done(&rules) {
+        state.set(Status::RoundOver);
+    }
+}
+
+// ラウンドの勝者を試合の成績に加える
+pub fn record_round_result(score_board: Res<ScoreBoard>, mut match_score: ResMut<MatchScore>) {
+    if let Some(id) = score_board.winner() {
+        *match_score.wins.entry(id).or_default() += 1;
+    }
+    match_score.rounds += 1;
+}
+
+// ゴールに着いたプレイヤーの勝ち (同時なら引き分け)
+pub fn goal_check(
+    goal_query: Query<&GridPos, With<Goal>>,
+    player_query: Query<(&Player, &GridPos)>,
+    mut score_board: ResMut<ScoreBoard>,
+    mut event: EventWriter<ScoreEvent>,
+    mut state: ResMut<NextState<Status>>,
+) {
+    let Ok(goal) = goal_query.get_single() else {
+        return;
+    };
+
+    let mut reached = false;
+    for (player, pos) in &player_query {
+        if pos == goal {
+            score_board.add(player.id, 1);
+            reached = true;
+        }
+    }
+    if reached {
+        event.send_default();
+        state.set(Status::RoundOver);
+    }
+}
+
+pub fn spawn_result_screen(
+    mut commands: Commands,
+    score_board: Res<ScoreBoard>,
+    match_score: Res<MatchScore>,
+    timer: Res<RoundTimer>,
+    game: Res<Game>,
+    rules: Res<RoundRules>,
+) {
+    let match_over = match_score.is_over(game.rounds);
+    let result = if match_over {
+        match match_score.winner(game.rounds) {
+            Some(id) => format!("P{} Wins the Match!", id + 1),
+            None => "Match Draw".to_string(),
+        }
+    } else {
+        match score_board.winner() {
+            Some(id) => format!("P{} Win!", id + 1),
+            None => "Draw".to_string(),
+        }
+    };
+    let (action, label) = if match_over {
+        (ButtonAction::NewMatch, "New Match")
+    } else {
+        (ButtonAction::NextRound, "Next Round")
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            ResultScreen,
+        ))
+        .with_children(|parent| {
+            if timer.is_up(game.time_limit) {
+                parent.spawn(TextBundle::from_section(
+                    "Time Up!",
+                    TextStyle {
+                        font_size: 40.,
+                        color: TEXT_COLOR,
+                        ..default()
+                    },
+                ));
+            }
+            parent.spawn(TextBundle::from_section(
+                result,
+                TextStyle {
+                    font_size: 80.,
+                    color: TEXT_COLOR,
+                    ..default()
+                },
+            ));
+            parent.spawn(TextBundle::from_section(
+                scores_text(&score_board.scores, rules.player_num),
+                TextStyle {
+                    font_size: 50.,
+                    color: TEXT_COLOR,
+                    ..default()
+                },
+            ));
+            parent.spawn(TextBundle::from_section(
+                format!(
+                    "Match: {}",
+                    scores_text(&match_score.wins, rules.player_num)
+                ),
+                TextStyle {
+                    font_size: 40.,
+                    color: TEXT_COLOR,
+                    ..default()
+                },
+            ));
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(260.),
+                            height: Val::Px(60.),
+                            margin: UiRect::top(Val::Px(20.)),
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::Center,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    action,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        label,
+                        TextStyle {
+                            font_size: 40.,
+                            color: Color::BLACK,
+                            ..default()
+                        },
+                    ));
+                });
+        });
+}
+
+pub fn despawn_result_screen(mut commands: Commands, query: Query<Entity, With<ResultScreen>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// 上の真ん中に試合の状況と時間を表示する
+pub fn setup_match_text(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                top: SCOREBOARD_TEXT_PADDING,
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 30.,
+                        color: TEXT_COLOR,
+                        ..default()
+                    },
+                ),
+                MatchText,
+            ));
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 30.,
+                        color: TEXT_COLOR,
+                        ..default()
+                    },
+                ),
+                TimerText,
+            ));
+        });
+}
+
+pub fn match_text_update(
+    match_score: Res<MatchScore>,
+    game: Res<Game>,
+    rules: Res<RoundRules>,
+    mut query: Query<&mut Text, With<MatchText>>,
+) {
+    if match_score.is_changed() || game.is_changed() || rules.is_changed() {
+        // 今のラウンド (試合が終わっていれば最後のラウンド)
+        let round = (match_score.rounds + 1).min(game.rounds);
+        for mut text in &mut query {
+            text.sections[0].value = format!(
+                "Round {}/{}  {}",
+                round,
+                game.rounds,
+                scores_text(&match_score.wins, rules.player_num)
+            );
+        }
+    }
+}
+
+// 制限時間がある時は残り時間、無い時は経過時間を表示する
+pub fn timer_text_update(
+    timer: Res<RoundTimer>,
+    game: Res<Game>,
+    mut query: Query<&mut Text, With<TimerText>>,
+) {
+    if timer.is_changed() || game.is_changed() {
+        let seconds = timer
+            .remaining(game.time_limit)
+            .map_or(timer.elapsed, f32::ceil) as u32;
+        for mut text in &mut query {
+            text.sections[0].value = format!("{}:{:02}", seconds / 60, seconds % 60);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn match_score(wins: &[(usize, usize)], rounds: usize) -> MatchScore {
+        MatchScore {
+            wins: wins.iter().copied().collect(),
+            rounds,
+        }
+    }
+
+    #[test]
+    fn majority_wins_the_match_early() {
+        // best of 5 は 3 勝で決まる
+        let score = match_score(&[(0, 3), (1, 1)], 4);
+        assert_eq!(score.winner(5), Some(0));
+        assert!(score.is_over(5));
+
+        let score = match_score(&[(0, 2), (1, 1)], 3);
+        assert_eq!(score.winner(5), None);
+        assert!(!score.is_over(5));
+    }
+
+    #[test]
+    fn leader_wins_after_the_last_round() {
+        // 引き分けがあって過半数に届かなくても、最後に多い方の勝ち
+        let score = match_score(&[(0, 1), (1, 2)], 5);
+        assert_eq!(score.winner(5), Some(1));
+        assert!(score.is_over(5));
+    }
+
+    #[test]
+    fn tied_match_is_a_draw() {
+        let score = match_score(&[(0, 1), (1, 1)], 3);
+        assert_eq!(score.winner(3), None);
+        assert!(score.is_over(3));
+
+        // 3 人以上でも一番多いのが二人なら引き分け
+        let score = match_score(&[(0, 2), (1, 2), (2, 1)], 5);
+        assert_eq!(score.winner(5), None);
+    }
+
+    #[test]
+    fn zero_scores_are_a_draw() {
+        assert_eq!(leader(&BTreeMap::new()), None);
+        assert_eq!(leader(&[(0, 0), (1, 0)].into_iter().collect()), None);
+        assert_eq!(match_score(&[], 3).winner(3), None);
+
+        let round = ScoreBoard {
+            scores: [(0, 0), (1, 3), (2, 1)].into_iter().collect(),
+        };
+        assert_eq!(round.winner(), Some(1));
+    }
+}
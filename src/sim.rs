@@ -0,0 +1,269 @@
+use std::{str::FromStr, time::Duration};
+
+use bevy::{input::InputPlugin, prelude::*, time::TimeUpdateStrategy};
+
+use crate::{
+    ai::Difficulty, round, round::RoundTimer, Game, GameMode, GamePlugin, ItemPlacement, MazeSeed,
+    RoundRules, ScoreBoard, Status, MAX_PLAYERS, MIN_PLAYERS,
+};
+
+// 一回の更新で進める時間 (秒)
+const TIMESTEP: f32 = 1. / 60.;
+
+// 終わらないラウンドを打ち切る時間 (秒)
+const DEFAULT_TIME_LIMIT: u32 = 180;
+
+// ウィンドウを出さずにコンピュータ同士で遊ばせる時の設定
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    rounds: usize,
+    // 最初のラウンドのシード値 (ラウンドごとに 1 ずつ増やす)
+    seed: u64,
+    difficulty: Difficulty,
+    players: usize,
+    mode: GameMode,
+    placement: ItemPlacement,
+    symmetric: bool,
+    time_limit: u32,
+}
+
+// ラウンドごとの結果
+#[derive(Resource, Default)]
+struct SimResults(Vec<RoundResult>);
+
+#[derive(Debug, PartialEq)]
+struct RoundResult {
+    winner: Option<usize>,
+    scores: Vec<usize>,
+    elapsed: f32,
+}
+
+impl SimConfig {
+    // `--simulate <ラウンド数>` が無ければ None
+    // 値が読めない時はエラーにして、ウィンドウを開かずに終わる
+    // 例: --simulate 1000 --seed 0 --difficulty hard --mode exit --placement balanced
+    pub fn from_args(args: &[String]) -> Result<Option<SimConfig>, String> {
+        let Some(rounds) = parse_value(args, "--simulate")? else {
+            return Ok(None);
+        };
+        let choice = |name: &str| -> Result<Option<String>, String> {
+            Ok(arg_value(args, name)?.map(str::to_lowercase))
+        };
+        let unknown = |name: &str, value: &str| format!("{name} に {value} は指定できません");
+
+        Ok(Some(SimConfig {
+            rounds,
+            seed: parse_value(args, "--seed")?.unwrap_or(0),
+            difficulty: match choice("--difficulty")?.as_deref() {
+                Some("easy") => Difficulty::Easy,
+                Some("normal") => Difficulty::Normal,
+                Some("hard") | None => Difficulty::Hard,
+                Some(other) => return Err(unknown("--difficulty", other)),
+            },
+            players: match parse_value(args, "--players")? {
+                Some(players @ MIN_PLAYERS..=MAX_PLAYERS) => players,
+                Some(players) => return Err(unknown("--players", &players.to_string())),
+                None => 2,
+            },
+            mode: match choice("--mode")?.as_deref() {
+                Some("exit") => GameMode::ExitRace,
+                Some("items") | None => GameMode::Items,
+                Some(other) => return Err(unknown("--mode", other)),
+            },
+            placement: match choice("--placement")?.as_deref() {
+                Some("balanced") => ItemPlacement::Balanced,
+                Some("random") | None => ItemPlacement::Random,
+                Some(other) => return Err(unknown("--placement", other)),
+            },
+            symmetric: args.iter().any(|arg| arg == "--symmetric"),
+            time_limit: parse_value(args, "--time-limit")?.unwrap_or(DEFAULT_TIME_LIMIT),
+        }))
+    }
+}
+
+// `name <値>` の値 (name が無ければ None)
+fn arg_value<'a>(args: &'a [String], name: &str) -> Result<Option<&'a str>, String> {
+    match args.iter().position(|arg| arg == name) {
+        Some(index) => match args.get(index + 1) {
+            Some(value) => Ok(Some(value)),
+            None => Err(format!("{name} の後に値がありません")),
+        },
+        None => Ok(None),
+    }
+}
+
+// `name <数値>` の値 (name が無ければ None)
+fn parse_value<T: FromStr>(args: &[String], name: &str) -> Result<Option<T>, String> {
+    match arg_value(args, name)? {
+        Some(value) => match value.parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => Err(format!("{name} の値が読めません: {value}")),
+        },
+        None => Ok(None),
+    }
+}
+
+// 決まった数のラウンドを遊ばせて、結果を表示する
+pub fn run(config: SimConfig) {
+    let results = play(&config);
+    report(&config, &results);
+}
+
+// 決まった数のラウンドを遊ばせる
+// 時間は実際の時間ではなく、一回の更新ごとに TIMESTEP ずつ進めるので、同じ設定なら同じ結果になる
+fn play(config: &SimConfig) -> Vec<RoundResult> {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, InputPlugin, GamePlugin))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            TIMESTEP,
+        )))
+        .insert_resource(MazeSeed(config.seed))
+        .init_resource::<SimResults>()
+        .add_systems(
+            OnEnter(Status::RoundOver),
+            next_round.after(round::record_round_result),
+        );
+
+    let mut game = app.world.resource_mut::<Game>();
+    game.player_num = config.players;
+    game.cpu = [Some(config.difficulty); MAX_PLAYERS];
+    game.mode = config.mode;
+    game.placement = config.placement;
+    game.symmetric = config.symmetric;
+    game.time_limit = config.time_limit;
+    game.init();
+
+    app.finish();
+    app.cleanup();
+    while app.world.resource::<SimResults>().0.len() < config.rounds {
+        app.update();
+    }
+
+    app.world
+        .remove_resource::<SimResults>()
+        .unwrap_or_default()
+        .0
+}
+
+// 結果を記録して、次のシード値でラウンドを始める
+fn next_round(
+    score_board: Res<ScoreBoard>,
+    timer: Res<RoundTimer>,
+    rules: Res<RoundRules>,
+    mut seed: ResMut<MazeSeed>,
+    mut results: ResMut<SimResults>,
+    mut state: ResMut<NextState<Status>>,
+) {
+    let result = RoundResult {
+        winner: score_board.winner(),
+        scores: (0..rules.player_num)
+            .map(|id| score_board.get(id))
+            .collect(),
+        elapsed: timer.elapsed,
+    };
+
+    let scores: Vec<String> = result.scores.iter().map(usize::to_string).collect();
+    let winner = result
+        .winner
+        .map_or("draw".to_string(), |id| format!("P{}", id + 1));
+    println!(
+        "seed {}: {winner} ({}) {:.1}s",
+        seed.0,
+        scores.join(" - "),
+        result.elapsed
+    );
+
+    results.0.push(result);
+    seed.0 = seed.0.wrapping_add(1);
+    state.set(Status::Shuffle);
+}
+
+// プレイヤーごとの勝率と平均スコアを表示する
+// 1 人目が多く勝っていれば、先に動くプレイヤーや配置が有利ということ
+fn report(config: &SimConfig, results: &[RoundResult]) {
+    let rounds = results.len().max(1) as f32;
+    let draws = results
+        .iter()
+        .filter(|result| result.winner.is_none())
+        .count();
+    let elapsed: f32 = results.iter().map(|result| result.elapsed).sum();
+
+    println!();
+    println!("{config:?}");
+    for id in 0..config.players {
+        let wins = results
+            .iter()
+            .filter(|result| result.winner == Some(id))
+            .count();
+        let score: usize = results.iter().map(|result| result.scores[id]).sum();
+        println!(
+            "P{}: {wins} wins ({:.1}%), average score {:.2}",
+            id + 1,
+            wins as f32 / rounds * 100.,
+            score as f32 / rounds
+        );
+    }
+    println!("draws: {draws} ({:.1}%)", draws as f32 / rounds * 100.);
+    println!("average time: {:.1}s", elapsed / rounds);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn unreadable_args_are_errors() {
+        assert!(SimConfig::from_args(&args(&["labyrinth"]))
+            .unwrap()
+            .is_none());
+        assert!(SimConfig::from_args(&args(&["--simulate", "10"]))
+            .unwrap()
+            .is_some());
+        for bad in [
+            &["--simulate", "abc"][..],
+            &["--simulate"],
+            &["--simulate", "10", "--seed", "x"],
+            &["--simulate", "10", "--players", "5"],
+            &["--simulate", "10", "--difficulty", "insane"],
+            &["--simulate", "10", "--mode", "race"],
+        ] {
+            assert!(SimConfig::from_args(&args(bad)).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_results() {
+        for sim_args in [
+            &["--simulate", "3", "--seed", "7"][..],
+            &[
+                "--simulate",
+                "2",
+                "--seed",
+                "7",
+                "--difficulty",
+                "easy",
+                "--placement",
+                "balanced",
+            ],
+            &[
+                "--simulate",
+                "2",
+                "--seed",
+                "7",
+                "--players",
+                "3",
+                "--mode",
+                "exit",
+            ],
+        ] {
+            let config = SimConfig::from_args(&args(sim_args)).unwrap().unwrap();
+            let first = play(&config);
+            assert_eq!(first.len(), config.rounds);
+            assert_eq!(first, play(&config), "{sim_args:?}");
+        }
+    }
+}